@@ -0,0 +1,208 @@
+use std::error::Error;
+use std::f32::consts::PI;
+use std::fmt;
+use std::str::FromStr;
+
+use chip8::Chip8;
+
+pub const DEFAULT_FREQUENCY: f32 = 440.0; // Hz
+pub const DEFAULT_VOLUME: f32 = 0.25;
+
+// How long the tone takes to fade in or out. Starting or stopping a wave
+// mid-cycle is heard as a click, a few milliseconds of ramp hides it while
+// still sounding instant.
+pub const RAMP_SECONDS: f32 = 0.005;
+
+// XO-CHIP audio patterns are 16 bytes, one bit per sample
+const PATTERN_BITS: usize = 128;
+
+// Shape of the buzzer tone
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Sine,
+    Triangle,
+}
+
+pub const WAVEFORMS: [Waveform; 3] = [Waveform::Square, Waveform::Sine, Waveform::Triangle];
+
+impl Waveform {
+    // Name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Waveform::Square => "square",
+            Waveform::Sine => "sine",
+            Waveform::Triangle => "triangle",
+        }
+    }
+
+    // Value between -1 and 1 at a point in the period, phase is 0 to 1
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (phase * 2.0 * PI).sin(),
+            Waveform::Triangle => 4.0 * (phase - 0.5).abs() - 1.0,
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownWaveform(pub String);
+
+impl fmt::Display for UnknownWaveform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = WAVEFORMS.iter().map(|waveform| waveform.name()).collect();
+        write!(
+            f,
+            "unknown waveform '{}', expected one of {}",
+            self.0,
+            names.join(", ")
+        )
+    }
+}
+
+impl Error for UnknownWaveform {}
+
+impl FromStr for Waveform {
+    type Err = UnknownWaveform;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            _ => Err(UnknownWaveform(name.to_string())),
+        }
+    }
+}
+
+// How the buzzer sounds, chosen by the user
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneSettings {
+    pub frequency: f32, // Hz
+    pub waveform: Waveform,
+    pub volume: f32, // 0 is silent, 1 is full scale
+}
+
+impl Default for ToneSettings {
+    fn default() -> Self {
+        ToneSettings {
+            frequency: DEFAULT_FREQUENCY,
+            waveform: Waveform::default(),
+            volume: DEFAULT_VOLUME,
+        }
+    }
+}
+
+/*
+    Generates the buzzer as mono f32 samples for an audio device callback.
+    It doesn't depend on any audio library, frontends copy the sound state in
+    once per frame with update() and pull samples out with fill().
+
+    The tone plays while the sound timer runs. An XO-CHIP program that loaded
+    an audio pattern hears that pattern at its pitch instead of the tone.
+*/
+pub struct Beeper {
+    settings: ToneSettings,
+    sample_rate: f32,
+
+    active: bool, // The tone should be sounding
+    gain: f32,    // Envelope ramping towards 1 while active and 0 otherwise
+    phase: f32,   // Position within the current period, 0 to 1
+
+    pattern: Option<[u8; 16]>, // XO-CHIP pattern played instead of the tone
+    pattern_rate: f32,         // Pattern bits played per second
+}
+
+impl Beeper {
+    pub fn new(sample_rate: u32, settings: ToneSettings) -> Self {
+        let mut beeper = Beeper {
+            settings,
+            sample_rate: sample_rate.max(1) as f32,
+
+            active: false,
+            gain: 0.0,
+            phase: 0.0,
+
+            pattern: None,
+            pattern_rate: 0.0,
+        };
+        beeper.set_settings(settings);
+        beeper
+    }
+
+    pub fn settings(&self) -> ToneSettings {
+        self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: ToneSettings) {
+        self.settings = settings;
+        self.settings.volume = settings.volume.clamp(0.0, 1.0);
+    }
+
+    // Copies the sound timer and XO-CHIP audio state from the machine
+    pub fn update(&mut self, chip: &Chip8) {
+        self.active = chip.sound_active();
+        self.pattern = chip.audio_pattern().cloned();
+        self.pattern_rate = chip.audio_playback_rate();
+    }
+
+    // Forces the tone on or off, e.g. to silence a paused machine
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+    }
+
+    // Fills a buffer with the next mono samples, each between -1 and 1
+    pub fn fill(&mut self, out: &mut [f32]) {
+        let ramp_step = 1.0 / (RAMP_SECONDS * self.sample_rate);
+        let target = if self.active { 1.0 } else { 0.0 };
+        let frequency = if self.pattern.is_some() {
+            self.pattern_rate / PATTERN_BITS as f32
+        } else {
+            self.settings.frequency
+        };
+
+        for sample in out.iter_mut() {
+            if self.gain < target {
+                self.gain = (self.gain + ramp_step).min(target);
+            } else if self.gain > target {
+                self.gain = (self.gain - ramp_step).max(target);
+            }
+
+            // restart the period while silent so every beep starts the same
+            if self.gain == 0.0 {
+                self.phase = 0.0;
+                *sample = 0.0;
+                continue;
+            }
+
+            let value = match self.pattern {
+                Some(ref pattern) => {
+                    let bit = (self.phase * PATTERN_BITS as f32) as usize % PATTERN_BITS;
+                    if pattern[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+                        1.0
+                    } else {
+                        -1.0
+                    }
+                }
+                None => self.settings.waveform.sample(self.phase),
+            };
+            *sample = value * self.gain * self.settings.volume;
+
+            self.phase = (self.phase + frequency / self.sample_rate).fract();
+        }
+    }
+}
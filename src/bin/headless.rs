@@ -0,0 +1,209 @@
+extern crate chip8_emu;
+
+use chip8_emu::chip8::TIMER_FREQUENCY;
+use chip8_emu::dump::DumpFormat;
+use chip8_emu::options::{option_value, MACHINE_USAGE};
+use chip8_emu::{Chip8, Chip8Error, MachineOptions, OptionError, StepOutcome};
+
+use std::env;
+use std::fs;
+use std::process;
+use std::str::FromStr;
+
+const USAGE: &str = "usage: chip8_headless ROM [options]
+
+Runs a ROM without a display and prints the final framebuffer.
+
+options:
+  --frames N                 run N frames at 60 Hz (default 60)
+  --cycles N                 run until N instructions have executed instead of a
+                             number of frames
+  --press FRAME:KEY[:FRAMES] hold hex KEY from FRAME for FRAMES frames (default 1),
+                             can be given more than once
+  --format ascii|pbm         framebuffer output format (default ascii)
+  --output PATH              write the framebuffer to PATH instead of stdout
+  -h, --help                 show this help";
+
+// How long to run for
+enum Limit {
+    Frames(u64),
+    Cycles(u64),
+}
+
+// A key held down for a span of frames
+struct KeyPress {
+    frame: u64,
+    key: usize,
+    frames: u64,
+}
+
+impl FromStr for KeyPress {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = spec.split(':').collect();
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err("expected FRAME:KEY or FRAME:KEY:FRAMES".to_string());
+        }
+
+        let frame = parts[0]
+            .parse()
+            .map_err(|err| format!("bad frame '{}': {}", parts[0], err))?;
+        let key = usize::from_str_radix(parts[1], 16)
+            .ok()
+            .filter(|key| *key < 16)
+            .ok_or_else(|| format!("bad key '{}', expected a hex digit 0-F", parts[1]))?;
+        let frames = match parts.get(2) {
+            Some(frames) => frames
+                .parse()
+                .map_err(|err| format!("bad frame count '{}': {}", frames, err))?,
+            None => 1,
+        };
+
+        Ok(KeyPress { frame, key, frames })
+    }
+}
+
+struct Options {
+    machine: MachineOptions,
+    limit: Limit,
+    presses: Vec<KeyPress>,
+    format: DumpFormat,
+    output: Option<String>,
+}
+
+// Returns None when the help was asked for
+fn parse_options() -> Result<Option<Options>, OptionError> {
+    let mut options = Options {
+        machine: MachineOptions::default(),
+        limit: Limit::Frames(u64::from(TIMER_FREQUENCY)),
+        presses: Vec::new(),
+        format: DumpFormat::default(),
+        output: None,
+    };
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if options.machine.parse_arg(&arg, &mut args)? {
+            continue;
+        }
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--frames" => options.limit = Limit::Frames(option_value(&arg, &mut args)?),
+            "--cycles" => options.limit = Limit::Cycles(option_value(&arg, &mut args)?),
+            "--press" => options.presses.push(option_value(&arg, &mut args)?),
+            "--format" => options.format = option_value(&arg, &mut args)?,
+            "--output" => options.output = Some(option_value(&arg, &mut args)?),
+            _ => return Err(OptionError::UnknownOption(arg)),
+        }
+    }
+
+    options.machine.rom_path()?;
+    Ok(Some(options))
+}
+
+// Sets the keypad to the keys the script holds during a frame
+fn apply_keys(chip: &mut Chip8, presses: &[KeyPress], frame: u64) {
+    chip.key = [0; 16];
+    for press in presses {
+        if frame >= press.frame && frame < press.frame.saturating_add(press.frames) {
+            chip.key[press.key] = 1;
+        }
+    }
+}
+
+// Whether every key press in the script has been released by this frame
+fn presses_over(presses: &[KeyPress], frame: u64) -> bool {
+    presses
+        .iter()
+        .all(|press| press.frame.saturating_add(press.frames) <= frame)
+}
+
+// Runs the machine until the limit is reached or the program exits
+fn run(chip: &mut Chip8, limit: &Limit, presses: &[KeyPress]) -> Result<(), Chip8Error> {
+    match *limit {
+        Limit::Frames(frames) => {
+            for frame in 0..frames {
+                apply_keys(chip, presses, frame);
+                chip.run_frame()?;
+                if chip.has_exited() {
+                    break;
+                }
+            }
+        }
+        Limit::Cycles(cycles) => {
+            // same pacing as run_frame, but stopping at an exact instruction.
+            // Only instructions that ran count, not cycles spent blocked.
+            let mut remainder = 0;
+            let mut executed = 0;
+            let mut frame = 0;
+
+            while executed < cycles {
+                apply_keys(chip, presses, frame);
+                let budget = u64::from(chip.speed()) + remainder;
+                remainder = budget % u64::from(TIMER_FREQUENCY);
+
+                for _ in 0..budget / u64::from(TIMER_FREQUENCY) {
+                    if executed == cycles {
+                        break;
+                    }
+                    match chip.emulate_cycle()? {
+                        StepOutcome::Executed(_) => executed += 1,
+                        StepOutcome::Exited => return Ok(()),
+                        // nothing feeds the input port here, and once the
+                        // script's key presses are over no key will come
+                        StepOutcome::WaitingForInput => return Ok(()),
+                        StepOutcome::WaitingForKey if presses_over(presses, frame) => return Ok(()),
+                        StepOutcome::WaitingForKey | StepOutcome::WaitingForVblank => break,
+                    }
+                }
+                chip.tick_timers();
+                frame += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let options = match parse_options() {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}\n\n{}", USAGE, MACHINE_USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}\nRun chip8_headless --help to see the options.", err);
+            process::exit(1);
+        }
+    };
+
+    let mut chip = options.machine.build();
+    // on stderr so it doesn't end up in the framebuffer dump
+    eprintln!("rng seed: {}", chip.seed());
+    let rom_path = options.machine.rom_path().unwrap_or_default();
+    if let Err(err) = chip.load_path(rom_path) {
+        eprintln!("couldn't load {}: {}", rom_path, err);
+        process::exit(1);
+    }
+
+    // the framebuffer is still written when the program fails, it usually
+    // shows how far it got
+    let result = run(&mut chip, &options.limit, &options.presses);
+
+    let dump = options.format.render(&chip);
+    match options.output {
+        Some(ref path) => {
+            if let Err(err) = fs::write(path, dump) {
+                eprintln!("couldn't write {}: {}", path, err);
+                process::exit(1);
+            }
+        }
+        None => print!("{}", dump),
+    }
+
+    if let Err(err) = result {
+        eprintln!("emulation halted: {}", err);
+        process::exit(2);
+    }
+}
@@ -0,0 +1,313 @@
+extern crate chip8_emu;
+#[cfg(unix)]
+extern crate libc;
+
+#[cfg(unix)]
+mod terminal {
+    use chip8_emu::chip8::TIMER_FREQUENCY;
+    use chip8_emu::options::MACHINE_USAGE;
+    use chip8_emu::{Chip8, Keymap, MachineOptions, OptionError};
+
+    use libc;
+
+    use std::env;
+    use std::io;
+    use std::io::prelude::*;
+    use std::mem;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    const USAGE: &str = "usage: chip8_term ROM [options]
+
+Plays a ROM in the terminal with the SDL window's default keys, 1234 QWER ASDF ZXCV
+for the 4x4 keypad. --keymap and key bindings only apply to the SDL window. Ctrl-C or
+Esc quits.
+
+options:
+  -h, --help         show this help";
+
+    // The CHIP-8 key a typed character presses in the default keymap, which
+    // names keys by the character they type
+    fn chip8_key(keymap: &Keymap, typed: u8) -> Option<usize> {
+        (0..16).find(|&key| {
+            keymap
+                .keys(key)
+                .iter()
+                .any(|name| name.len() == 1 && name.as_bytes()[0].eq_ignore_ascii_case(&typed))
+        })
+    }
+
+    // Terminals only report key presses, never releases. A key counts as held
+    // for this many frames after its last press, long enough to bridge the
+    // gap before the keyboard's auto-repeat kicks in.
+    const KEY_HOLD_FRAMES: u32 = 30;
+
+    // Puts the terminal in raw, non-blocking mode and restores it when dropped,
+    // including when the emulator panics
+    struct RawMode {
+        original: libc::termios,
+    }
+
+    impl RawMode {
+        fn enable() -> io::Result<Self> {
+            unsafe {
+                let mut original: libc::termios = mem::zeroed();
+                if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                let mut raw = original;
+                raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+                raw.c_iflag &= !(libc::IXON | libc::ICRNL);
+                // reads return straight away with whatever has been typed
+                raw.c_cc[libc::VMIN] = 0;
+                raw.c_cc[libc::VTIME] = 0;
+                if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+
+                Ok(RawMode { original })
+            }
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // leave the alternate screen and show the cursor again
+            print!("\x1b[0m\x1b[?25h\x1b[?1049l");
+            let _ = io::stdout().flush();
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+            }
+        }
+    }
+
+    /*
+        Draws the framebuffer with half-block characters, two pixel rows per
+        terminal row. The last frame's rows are kept so only the rows that
+        changed are rewritten.
+    */
+    struct Screen {
+        rows: Vec<String>,
+        size: (usize, usize),
+        status: String,
+    }
+
+    impl Screen {
+        fn new() -> Self {
+            Screen {
+                rows: Vec::new(),
+                size: (0, 0),
+                status: String::new(),
+            }
+        }
+
+        fn draw(&mut self, chip: &Chip8, out: &mut String) {
+            let (width, height) = (chip.display_width(), chip.display_height());
+            if (width, height) != self.size {
+                // the display mode changed, start over from a blank screen
+                self.size = (width, height);
+                self.rows = vec![String::new(); height.div_ceil(2)];
+                self.status.clear();
+                out.push_str("\x1b[2J");
+            }
+
+            for (index, row) in self.rows.iter_mut().enumerate() {
+                let top = &chip.gfx[index * 2 * width..(index * 2 + 1) * width];
+                let bottom = chip
+                    .gfx
+                    .get((index * 2 + 1) * width..(index * 2 + 2) * width);
+
+                let mut line = String::with_capacity(width * 3);
+                for x in 0..width {
+                    let upper = top[x] != 0;
+                    let lower = bottom.is_some_and(|bottom| bottom[x] != 0);
+                    line.push(match (upper, lower) {
+                        (true, true) => '█',
+                        (true, false) => '▀',
+                        (false, true) => '▄',
+                        (false, false) => ' ',
+                    });
+                }
+
+                if *row != line {
+                    out.push_str(&format!("\x1b[{};1H{}", index + 1, line));
+                    *row = line;
+                }
+            }
+        }
+
+        // Shown on the line below the display
+        fn set_status(&mut self, status: &str, out: &mut String) {
+            if self.status != status {
+                self.status = status.to_string();
+                out.push_str(&format!("\x1b[{};1H\x1b[2K{}", self.rows.len() + 1, status));
+            }
+        }
+    }
+
+    // Returns None when the help was asked for
+    fn parse_options() -> Result<Option<MachineOptions>, OptionError> {
+        let mut machine = MachineOptions::default();
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            if machine.parse_arg(&arg, &mut args)? {
+                continue;
+            }
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(None),
+                _ => return Err(OptionError::UnknownOption(arg)),
+            }
+        }
+
+        machine.rom_path()?;
+        Ok(Some(machine))
+    }
+
+    pub fn main() -> i32 {
+        let machine = match parse_options() {
+            Ok(Some(machine)) => machine,
+            Ok(None) => {
+                println!("{}\n\n{}", USAGE, MACHINE_USAGE);
+                return 0;
+            }
+            Err(err) => {
+                eprintln!("{}\nRun chip8_term --help to see the options.", err);
+                return 1;
+            }
+        };
+
+        let mut chip = machine.build();
+        let rom_path = machine.rom_path().unwrap_or_default();
+        if let Err(err) = chip.load_path(rom_path) {
+            eprintln!("couldn't load {}: {}", rom_path, err);
+            return 1;
+        }
+
+        let raw_mode = match RawMode::enable() {
+            Ok(raw_mode) => raw_mode,
+            Err(err) => {
+                eprintln!("couldn't put the terminal in raw mode: {}", err);
+                return 1;
+            }
+        };
+        let result = run(&mut chip);
+        drop(raw_mode);
+
+        println!("rng seed: {}", chip.seed());
+        match result {
+            Ok(()) => 0,
+            Err(err) => {
+                eprintln!("terminal error: {}", err);
+                1
+            }
+        }
+    }
+
+    fn run(chip: &mut Chip8) -> io::Result<()> {
+        let stdin = io::stdin();
+        let mut stdin = stdin.lock();
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+
+        // alternate screen, hidden cursor
+        stdout.write_all(b"\x1b[?1049h\x1b[?25l\x1b[2J")?;
+
+        let keymap = Keymap::default();
+        let mut screen = Screen::new();
+        chip.draw_flag = true;
+        let mut held = [0u32; 16];
+        let mut input = [0u8; 64];
+        let mut halted: Option<String> = None;
+        let mut was_beeping = false;
+        let mut out = String::new();
+
+        let frame_duration = Duration::from_secs(1) / TIMER_FREQUENCY;
+        let mut next_frame = Instant::now();
+
+        loop {
+            let count = stdin.read(&mut input)?;
+            let mut index = 0;
+            while index < count {
+                let byte = input[index];
+                index += 1;
+                match byte {
+                    // Ctrl-C
+                    0x03 => return Ok(()),
+                    // Esc on its own quits, as part of a sequence (arrow
+                    // keys and the like) the rest of the sequence is skipped
+                    0x1B if index == count => return Ok(()),
+                    0x1B => {
+                        while index < count
+                            && !(input[index].is_ascii_alphabetic() || input[index] == b'~')
+                        {
+                            index += 1;
+                        }
+                        index += 1;
+                    }
+                    _ => {
+                        if let Some(i) = chip8_key(&keymap, byte) {
+                            held[i] = KEY_HOLD_FRAMES;
+                        }
+                    }
+                }
+            }
+
+            for (i, frames) in held.iter_mut().enumerate() {
+                chip.key[i] = (*frames > 0) as u8;
+                *frames = frames.saturating_sub(1);
+            }
+
+            if halted.is_none() {
+                match chip.run_frame() {
+                    Ok(()) if chip.has_exited() => halted = Some("program exited".to_string()),
+                    Ok(()) => {}
+                    Err(err) => halted = Some(format!("emulation halted: {}", err)),
+                }
+            }
+
+            // the terminal bell stands in for the buzzer
+            let beeping = chip.sound_active() && halted.is_none();
+            if beeping && !was_beeping {
+                out.push('\x07');
+            }
+            was_beeping = beeping;
+
+            if chip.draw_flag {
+                chip.draw_flag = false;
+                screen.draw(chip, &mut out);
+            }
+            let status = match halted {
+                Some(ref message) => format!("{} - Ctrl-C quits", message),
+                None => format!("{} - Ctrl-C quits", chip.platform()),
+            };
+            screen.set_status(&status, &mut out);
+
+            if !out.is_empty() {
+                stdout.write_all(out.as_bytes())?;
+                stdout.flush()?;
+                out.clear();
+            }
+
+            next_frame += frame_duration;
+            let now = Instant::now();
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+            } else {
+                next_frame = now;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn main() {
+    std::process::exit(terminal::main());
+}
+
+#[cfg(not(unix))]
+fn main() {
+    eprintln!("the terminal frontend needs a Unix terminal");
+    std::process::exit(1);
+}
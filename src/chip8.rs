@@ -1,12 +1,37 @@
-extern crate rand;
-
-use std::error::Error;
 use std::fs::File;
 use std::io::prelude::*;
 use std::path::Path;
 
-#[cfg_attr(rustfmt, rustfmt_skip)]
-const CHIP8_FONTSET: [u8; 80] = [
+use error::{Chip8Error, LoadError, StateError};
+use instruction;
+use instruction::Instruction;
+use platform::Platform;
+use quirks::Quirks;
+use rng::Rng;
+use state::{StateReader, StateWriter};
+
+pub const SCREEN_WIDTH: usize = 64;
+pub const SCREEN_HEIGHT: usize = 32; // 64 on HIRES CHIP-8, see Platform::screen_size()
+pub const HIRES_SCREEN_WIDTH: usize = 128; // SUPER-CHIP hi-res mode
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
+pub const MEMORY_SIZE: usize = 4096; // Every platform but XO-CHIP, which has 64 KB
+pub const DEFAULT_LOAD_ADDRESS: u16 = 0x200;
+
+pub const DEFAULT_PITCH: u8 = 64; // XO-CHIP pitch for 4000 samples per second
+
+// CHIP-8X colours are VP-590 codes: bit 0 red, bit 1 blue, bit 2 green. The
+// foreground colour is set per zone, 8 pixels wide and one row tall.
+pub const COLOR_ZONE_WIDTH: usize = 8;
+pub const COLOR_ZONE_COLUMNS: usize = SCREEN_WIDTH / COLOR_ZONE_WIDTH;
+pub const COLOR_ZONE_ROWS: usize = SCREEN_HEIGHT;
+pub const DEFAULT_FOREGROUND: u8 = 1; // Red
+pub const BACKGROUND_COLORS: [u8; 4] = [2, 0, 4, 1]; // Blue, black, green, red
+
+pub const TIMER_FREQUENCY: u32 = 60; // Delay and sound timers tick at 60 Hz
+pub const DEFAULT_SPEED: u32 = 700; // Instructions per second
+
+#[rustfmt::skip]
+pub const CHIP8_FONTSET: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, //0
     0x20, 0x60, 0x20, 0x20, 0x70, //1
     0xF0, 0x10, 0xF0, 0x80, 0xF0, //2
@@ -25,12 +50,55 @@ const CHIP8_FONTSET: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80  //F
 ];
 
+// 8x10 SUPER-CHIP font for FX30, stored right after the small font. The
+// original only had the digits, A-F are the Octo glyphs.
+pub const BIG_FONT_ADDRESS: usize = 0x50;
+
+#[rustfmt::skip]
+pub const SCHIP_BIG_FONTSET: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, //0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, //1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, //2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, //3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, //4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, //5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, //6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, //7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, //8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, //9
+    0x18, 0x3C, 0x66, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, //A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, //B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, //C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, //D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, //E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0  //F
+];
+
+// What a successfully executed cycle did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepOutcome {
+    Executed(Instruction), // Instruction ran and the program counter moved on
+    WaitingForKey,         // FX0A is blocking until a key is pressed
+    WaitingForVblank,      // DXYN is blocking until the next timer tick
+    WaitingForInput,       // FXFB is blocking until the input port has a byte
+    Exited,                // 00FD stopped the program
+}
+
+// Where a ROM ended up in memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RomInfo {
+    pub size: usize,        // Length of the ROM in bytes
+    pub load_address: u16,  // Address of the first byte
+    pub start_address: u16, // Where execution starts, usually the load address
+}
+
+#[derive(Clone)]
 pub struct Chip8 {
     stack: [u16; 16], // Stack
     sp: u16,          // Stack pointer
 
-    memory: [u8; 4096], // Memory 4kB
-    v: [u8; 16],        // V registers (V0-VF)
+    memory: Vec<u8>, // Memory 4kB, 64kB on XO-CHIP
+    v: [u8; 16],     // V registers (V0-VF)
 
     pc: u16,         // Program counter
     opcode: u16,     // Current opcode
@@ -38,29 +106,82 @@ pub struct Chip8 {
     delay_timer: u8, // Delay Timer
     sound_timer: u8, // Sound timer
 
-    pub gfx: [u8; 64 * 32], // Graphics buffer
-    pub key: [u8; 16],      // Keypad
-    pub draw_flag: bool,    // Indicates a draw has occured
+    load_address: u16, // Where ROMs get loaded
+
+    platform: Platform, // Interpreter variant being emulated
+    hires: bool,        // SUPER-CHIP 128x64 mode is active
+    flags: [u8; 16],    // SUPER-CHIP RPL user flags for FX75/FX85
+    exited: bool,       // 00FD has been executed
+    planes: u8,         // XO-CHIP bitplanes selected by FN01, always 1 otherwise
+
+    audio_pattern: Option<[u8; 16]>, // XO-CHIP 1-bit sample loaded by F002
+    pitch: u8,                       // XO-CHIP playback pitch set by FX3A
+
+    background: u8, // CHIP-8X index into BACKGROUND_COLORS, stepped by 02A0
+    zone_colors: [u8; COLOR_ZONE_COLUMNS * COLOR_ZONE_ROWS], // CHIP-8X foreground colours
+    output_port: u8, // CHIP-8X last byte written by FXF8
+    input_port: Option<u8>, // CHIP-8X byte waiting to be read by FXFB
+
+    quirks: Quirks,     // Interpreter specific behaviours
+    vblank_ready: bool, // No sprite has been drawn since the last 60 Hz tick
+    rng: Rng,           // Random source for CXNN
+
+    speed: u32,           // Instructions executed per second
+    cycle_remainder: u32, // Carries the fractional cycles between frames
+
+    pub gfx: Vec<u8>,    // Graphics buffer, one bit per bitplane for each pixel
+    pub key: [u8; 16],   // Keypad
+    pub key2: [u8; 16],  // Second keypad, only read by CHIP-8X
+    pub draw_flag: bool, // Indicates a draw has occured
 }
 
 impl Chip8 {
-    // Initalize
-    pub fn new() -> Self {
+    // Initalize a plain CHIP-8
+    pub fn new(quirks: Quirks) -> Self {
+        Self::with_platform(Platform::Chip8, quirks)
+    }
+
+    // Initalize for a platform, usually with platform.quirks()
+    pub fn with_platform(platform: Platform, quirks: Quirks) -> Self {
         let mut chip = Chip8 {
             stack: [0; 16],
             sp: 0,
 
-            memory: [0; 4096],
+            memory: vec![0; platform.memory_size()],
             v: [0; 16],
 
-            pc: 0x200,
+            pc: platform.load_address(),
             opcode: 0,
             i: 0,
             delay_timer: 0,
             sound_timer: 0,
 
-            gfx: [0; 64 * 32],
+            load_address: platform.load_address(),
+
+            platform,
+            hires: false,
+            flags: [0; 16],
+            exited: false,
+            planes: 1,
+
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+
+            background: 0,
+            zone_colors: [DEFAULT_FOREGROUND; COLOR_ZONE_COLUMNS * COLOR_ZONE_ROWS],
+            output_port: 0,
+            input_port: None,
+
+            quirks,
+            vblank_ready: true,
+            rng: Rng::from_entropy(),
+
+            speed: DEFAULT_SPEED,
+            cycle_remainder: 0,
+
+            gfx: vec![0; platform.screen_size().0 * platform.screen_size().1],
             key: [0; 16],
+            key2: [0; 16],
             draw_flag: false,
         };
 
@@ -68,450 +189,1220 @@ impl Chip8 {
         for (i, element) in CHIP8_FONTSET.iter().enumerate() {
             chip.memory[i] = element.to_owned();
         }
+        if platform.has_super_chip() {
+            chip.memory[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + SCHIP_BIG_FONTSET.len()]
+                .copy_from_slice(&SCHIP_BIG_FONTSET);
+        }
 
-        return chip;
+        chip
     }
 
-    pub fn load(&mut self, game_name: &str) {
-        let path = Path::new(game_name);
-        let display = path.display();
+    // Sets the address used by the next load, platform.load_address() unless
+    // changed
+    pub fn set_load_address(&mut self, addr: u16) {
+        self.load_address = addr;
+    }
 
-        let mut file = match File::open(&path) {
-            Err(why) => panic!("couldn't open {}: {}", display, why.description()),
-            Ok(file) => file,
-        };
+    pub fn load_address(&self) -> u16 {
+        self.load_address
+    }
 
+    // Copies a ROM into memory at the load address and points the program
+    // counter at it. Memory is left untouched if the ROM doesn't fit or the
+    // load address is outside memory.
+    pub fn load_bytes(&mut self, rom: &[u8]) -> Result<RomInfo, LoadError> {
+        let start = self.load_address as usize;
+        if start >= self.memory.len() {
+            return Err(LoadError::AddressOutOfRange {
+                addr: self.load_address,
+                memory_size: self.memory.len(),
+            });
+        }
+        let capacity = self.memory.len() - start;
+
+        if rom.len() > capacity {
+            return Err(LoadError::TooLarge {
+                size: rom.len(),
+                capacity,
+            });
+        }
+
+        // clear out whatever a previous ROM left behind
+        for byte in self.memory[start..].iter_mut() {
+            *byte = 0;
+        }
+        self.memory[start..start + rom.len()].copy_from_slice(rom);
+        self.pc = self.load_address.wrapping_add(self.platform.entry_offset());
+
+        Ok(RomInfo {
+            size: rom.len(),
+            load_address: self.load_address,
+            start_address: self.pc,
+        })
+    }
+
+    pub fn load_reader<R: Read>(&mut self, mut reader: R) -> Result<RomInfo, LoadError> {
         let mut rom = Vec::new();
-        match file.read_to_end(&mut rom) {
-            Err(why) => panic!("couldn't read {}: {}", display, why.description()),
-            Ok(_) => {}
+        reader.read_to_end(&mut rom)?;
+        self.load_bytes(&rom)
+    }
+
+    pub fn load_path<P: AsRef<Path>>(&mut self, path: P) -> Result<RomInfo, LoadError> {
+        let file = File::open(path)?;
+        self.load_reader(file)
+    }
+
+    // Memory, registers and framebuffer, for debuggers and other tools
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    pub fn v(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn opcode(&self) -> u16 {
+        self.opcode
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    // Size of the active display mode, gfx holds width * height pixels
+    pub fn display_width(&self) -> usize {
+        if self.hires {
+            HIRES_SCREEN_WIDTH
+        } else {
+            self.platform.screen_size().0
         }
+    }
 
-        if rom.len() < (4096 - 512) {
-            for (i, byte) in rom.iter().enumerate() {
-                self.memory[i + 512] = byte.to_owned();
-            }
+    pub fn display_height(&self) -> usize {
+        if self.hires {
+            HIRES_SCREEN_HEIGHT
         } else {
-            println!("Rom too big to fit in memory");
+            self.platform.screen_size().1
         }
     }
 
-    pub fn emulate_cycle(&mut self) {
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    // SUPER-CHIP RPL user flags
+    pub fn flags(&self) -> &[u8; 16] {
+        &self.flags
+    }
+
+    // True once the program has stopped itself with 00FD
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    // XO-CHIP bitplanes that drawing and clearing currently affect
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    // XO-CHIP sample played while the sound timer runs, one bit per sample.
+    // None until the program loads one, frontends use a plain tone then.
+    pub fn audio_pattern(&self) -> Option<&[u8; 16]> {
+        self.audio_pattern.as_ref()
+    }
+
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    // Samples per second the audio pattern is played back at
+    pub fn audio_playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+
+    // CHIP-8X background colour as a VP-590 colour code
+    pub fn background_color(&self) -> u8 {
+        BACKGROUND_COLORS[self.background as usize]
+    }
+
+    // CHIP-8X foreground colour of the zone holding a 64x32 pixel
+    pub fn zone_color(&self, x: usize, y: usize) -> u8 {
+        let column = (x / COLOR_ZONE_WIDTH) % COLOR_ZONE_COLUMNS;
+        self.zone_colors[column + (y % COLOR_ZONE_ROWS) * COLOR_ZONE_COLUMNS]
+    }
+
+    // CHIP-8X byte most recently written to the output port. The VP-595
+    // sound board took this as the tone frequency.
+    pub fn output_port(&self) -> u8 {
+        self.output_port
+    }
+
+    // Latches a byte on the CHIP-8X input port for the next FXFB to read
+    pub fn set_input_port(&mut self, value: u8) {
+        self.input_port = Some(value);
+    }
+
+    // Captures the whole machine in the format documented in state.rs
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+
+        state.u8(self.platform.id());
+        state.bytes(&self.memory);
+        state.bytes(&self.v);
+        for entry in self.stack.iter() {
+            state.u16(*entry);
+        }
+        state.u16(self.sp);
+        state.u16(self.pc);
+        state.u16(self.i);
+        state.u16(self.opcode);
+        state.u8(self.delay_timer);
+        state.u8(self.sound_timer);
+        state.u8(self.vblank_ready as u8);
+        state.u64(self.rng.seed());
+        state.u64(self.rng.state());
+        state.u8(self.hires as u8);
+        state.bytes(&self.gfx);
+        state.bytes(&self.key);
+        state.bytes(&self.flags);
+        state.u8(self.exited as u8);
+        state.u8(self.planes);
+        state.u8(self.audio_pattern.is_some() as u8);
+        state.bytes(&self.audio_pattern.unwrap_or([0; 16]));
+        state.u8(self.pitch);
+        state.u8(self.background);
+        state.bytes(&self.zone_colors);
+        state.bytes(&self.key2);
+        state.u8(self.output_port);
+        state.u8(self.input_port.is_some() as u8);
+        state.u8(self.input_port.unwrap_or(0));
+
+        state.finish()
+    }
+
+    // Restores a state from save_state. The machine is only modified once the
+    // whole state has been read and validated.
+    pub fn load_state(&mut self, state: &[u8]) -> Result<(), StateError> {
+        let mut state = StateReader::new(state)?;
+        if state.u8()? != self.platform.id() {
+            return Err(StateError::PlatformMismatch);
+        }
+        let mut restored = self.clone();
+
+        let memory_size = restored.memory.len();
+        restored.memory.copy_from_slice(state.bytes(memory_size)?);
+        restored.v.copy_from_slice(state.bytes(16)?);
+        for entry in restored.stack.iter_mut() {
+            *entry = state.u16()?;
+        }
+        restored.sp = state.u16()?;
+        restored.pc = state.u16()?;
+        restored.i = state.u16()?;
+        restored.opcode = state.u16()?;
+        restored.delay_timer = state.u8()?;
+        restored.sound_timer = state.u8()?;
+        restored.vblank_ready = state.u8()? != 0;
+        let seed = state.u64()?;
+        restored.rng = Rng::new(seed);
+        restored.rng.set_state(state.u64()?);
+        restored.hires = state.u8()? != 0;
+        let pixels = restored.display_width() * restored.display_height();
+        restored.gfx = state.bytes(pixels)?.to_vec();
+        restored.key.copy_from_slice(state.bytes(16)?);
+        restored.flags.copy_from_slice(state.bytes(16)?);
+        restored.exited = state.u8()? != 0;
+        restored.planes = state.u8()? & 0x3;
+        let has_pattern = state.u8()? != 0;
+        let mut pattern = [0; 16];
+        pattern.copy_from_slice(state.bytes(16)?);
+        restored.audio_pattern = if has_pattern { Some(pattern) } else { None };
+        restored.pitch = state.u8()?;
+        restored.background = state.u8()? % BACKGROUND_COLORS.len() as u8;
+        let zones = COLOR_ZONE_COLUMNS * COLOR_ZONE_ROWS;
+        restored.zone_colors.copy_from_slice(state.bytes(zones)?);
+        restored.key2.copy_from_slice(state.bytes(16)?);
+        restored.output_port = state.u8()?;
+        let has_input = state.u8()? != 0;
+        let input = state.u8()?;
+        restored.input_port = if has_input { Some(input) } else { None };
+        state.finish()?;
+
+        if restored.sp as usize > restored.stack.len() {
+            return Err(StateError::InvalidValue("stack pointer"));
+        }
+
+        restored.draw_flag = true;
+        *self = restored;
+        Ok(())
+    }
+
+    // Fetches, decodes and executes one instruction. On error the machine is
+    // left as it was before the failing instruction.
+    pub fn emulate_cycle(&mut self) -> Result<StepOutcome, Chip8Error> {
+        if self.exited {
+            return Ok(StepOutcome::Exited);
+        }
+        if self.pc as usize + 1 >= self.memory.len() {
+            return Err(Chip8Error::PcOutOfBounds { pc: self.pc });
+        }
+
         let first_byte = (self.memory[self.pc as usize] as u16) << 8;
         let second_byte = self.memory[self.pc as usize + 1] as u16;
         self.opcode = first_byte | second_byte;
 
-        // print opcodes
-        // println!("\n\n opcode -> {:x} \n", self.opcode);
-
-        // // print registers
-        // println!("---registers---");
-        // for reg in self.v.iter() {
-        //     print!("{:x}, ", reg);
-        // }
-        // print!("\n");
-        // println!("---end registers---");
-
-        // println!("i is {}", self.i);
-
-        // println!("--- begin memory dump ---");
-        // for byte in self.memory.iter() {
-        //     print!("{:x}, ", byte);
-        // }
-        // println!("\n--- end memory dump ---");
-
-        match self.opcode & 0xF000 {
-            // 00E_
-            0x0000 => match self.opcode & 0x000F {
-                // 00E0 - Clear screen
-                0x0000 => {
-                    self.gfx = [0; 64 * 32];
-                    self.draw_flag = true;
-                    self.pc += 2;
-                }
-                //00EE - Return from subroutine
-                0x000E => {
-                    self.sp -= 1;
-                    self.pc = self.stack[self.sp as usize];
-                    self.pc += 2;
+        let instruction = match instruction::decode_for(self.platform, self.opcode) {
+            Ok(instruction) => instruction,
+            Err(_) => {
+                return Err(Chip8Error::UnknownOpcode {
+                    addr: self.pc,
+                    opcode: self.opcode,
+                })
+            }
+        };
+        self.execute(instruction)
+    }
+
+    // Decrements the delay and sound timers, call this at TIMER_FREQUENCY.
+    // This is also the vertical blank that DXYN waits for with display_wait.
+    pub fn tick_timers(&mut self) {
+        self.vblank_ready = true;
+
+        if self.delay_timer > 0 {
+            self.delay_timer -= 1;
+        }
+
+        if self.sound_timer > 0 {
+            self.sound_timer -= 1;
+        }
+    }
+
+    // Runs one 60 Hz frame: as many cycles as the speed allows, then a timer
+    // tick. Frontends call this once per frame so timing doesn't depend on
+    // how fast the host can execute instructions.
+    pub fn run_frame(&mut self) -> Result<(), Chip8Error> {
+        // in u64 so speeds near u32::MAX don't overflow
+        let budget = u64::from(self.speed) + u64::from(self.cycle_remainder);
+        let cycles = budget / u64::from(TIMER_FREQUENCY);
+        self.cycle_remainder = (budget % u64::from(TIMER_FREQUENCY)) as u32;
+
+        for _ in 0..cycles {
+            match self.emulate_cycle()? {
+                StepOutcome::WaitingForKey
+                | StepOutcome::WaitingForVblank
+                | StepOutcome::WaitingForInput
+                | StepOutcome::Exited => break,
+                StepOutcome::Executed(_) => {}
+            }
+        }
+
+        self.tick_timers();
+        Ok(())
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    // Reseeds the random number generator, runs with the same seed and
+    // inputs are identical
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    // Seed the random number generator started from
+    pub fn seed(&self) -> u64 {
+        self.rng.seed()
+    }
+
+    // Sets the CPU speed in instructions per second
+    pub fn set_speed(&mut self, instructions_per_second: u32) {
+        self.speed = instructions_per_second;
+        self.cycle_remainder = 0;
+    }
+
+    // Sets the CPU speed in instructions per 60 Hz frame, saturating at
+    // u32::MAX instructions per second
+    pub fn set_cycles_per_frame(&mut self, cycles: u32) {
+        self.set_speed(cycles.saturating_mul(TIMER_FREQUENCY));
+    }
+
+    pub fn speed(&self) -> u32 {
+        self.speed
+    }
+
+    // True while the sound timer is running and the buzzer should sound
+    pub fn sound_active(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    // Executes an already decoded instruction, advancing the program counter.
+    // The counter wraps at 16 bits, running off the end of memory is caught
+    // by the next fetch.
+    pub fn execute(&mut self, instruction: Instruction) -> Result<StepOutcome, Chip8Error> {
+        match instruction {
+            // 00CN - Scrolls the display down by N pixels
+            Instruction::ScrollDown { n } => {
+                self.scroll(0, n as isize);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 00DN - Scrolls the display up by N pixels
+            Instruction::ScrollUp { n } => {
+                self.scroll(0, -(n as isize));
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 00E0 - Clear screen, 0230 on HIRES CHIP-8
+            Instruction::ClearScreen => {
+                self.clear_display();
+                self.draw_flag = true;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 00EE - Return from subroutine
+            Instruction::Return => {
+                if self.sp == 0 {
+                    return Err(Chip8Error::StackUnderflow { addr: self.pc });
                 }
-                _ => println!("Unknown opcode"),
-            },
+                self.sp -= 1;
+                self.pc = self.stack[self.sp as usize];
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 00FB - Scrolls the display right by 4 pixels
+            Instruction::ScrollRight => {
+                self.scroll(4, 0);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 00FC - Scrolls the display left by 4 pixels
+            Instruction::ScrollLeft => {
+                self.scroll(-4, 0);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 00FD - Exits the interpreter
+            Instruction::Exit => {
+                self.exited = true;
+                self.pc = self.pc.wrapping_add(2);
+                return Ok(StepOutcome::Exited);
+            }
+            // 00FE - Switches to the 64x32 display, clearing it
+            Instruction::LowRes => {
+                self.set_hires(false);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 00FF - Switches to the 128x64 display, clearing it
+            Instruction::HighRes => {
+                self.set_hires(true);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 02A0 - Steps the background colour through blue, black, green and red
+            Instruction::CycleBackground => {
+                self.background = (self.background + 1) % BACKGROUND_COLORS.len() as u8;
+                self.draw_flag = true;
+                self.pc = self.pc.wrapping_add(2);
+            }
             // 1NNN - Jumps to address NNN
-            0x1000 => {
-                self.pc = self.opcode & 0x0FFF;
+            Instruction::Jump { nnn } => {
+                self.pc = nnn;
             }
             // 2NNN - Calls subroutine at NNN
-            0x2000 => {
+            Instruction::Call { nnn } => {
+                if self.sp as usize >= self.stack.len() {
+                    return Err(Chip8Error::StackOverflow { addr: self.pc });
+                }
                 self.stack[self.sp as usize] = self.pc;
                 self.sp += 1;
-                self.pc = self.opcode & 0x0FFF;
+                self.pc = nnn;
             }
             // 3XNN - Skips the next instruction if VX = NN
-            0x3000 => {
-                let x = (self.opcode & 0x0F00) >> 8;
-                let vx = self.v[x as usize] as u16;
-
-                if vx == (self.opcode & 0x00FF) {
-                    self.pc += 4;
-                } else {
-                    self.pc += 2;
-                }
+            Instruction::SkipEqImm { x, nn } => {
+                let skip = self.v[x as usize] == nn;
+                self.skip_if(skip);
             }
             // 4XNN - Skips the next instruction if VX != NN
-            0x4000 => {
-                let x = (self.opcode & 0x0F00) >> 8;
-                let vx = self.v[x as usize] as u16; // not sure if this cast is correct
-                let nn = self.opcode & 0x00FF;
-
-                if vx != nn {
-                    self.pc += 4;
-                } else {
-                    self.pc += 2;
-                }
+            Instruction::SkipNeImm { x, nn } => {
+                let skip = self.v[x as usize] != nn;
+                self.skip_if(skip);
             }
             // 5XY0 - Skips the next instruction if VX == VY
-            0x5000 => {
-                let x = (self.opcode & 0x0F00) >> 8;
-                let vx = self.v[x as usize];
-                let y = (self.opcode & 0x00F0) >> 4;
-                let vy = self.v[y as usize];
-
-                if vx == vy {
-                    self.pc += 4;
-                } else {
-                    self.pc += 2;
+            Instruction::SkipEqReg { x, y } => {
+                let skip = self.v[x as usize] == self.v[y as usize];
+                self.skip_if(skip);
+            }
+            /*
+                5XY1 - Adds VY to VX one nibble at a time. Each nibble wraps
+                at 8 without carrying, which keeps CHIP-8X colour values and
+                zone coordinates in range.
+            */
+            Instruction::AddNibbles { x, y } => {
+                let (vx, vy) = (self.v[x as usize], self.v[y as usize]);
+                let high = ((vx >> 4) + (vy >> 4)) & 0x7;
+                let low = ((vx & 0xF) + (vy & 0xF)) & 0x7;
+                self.v[x as usize] = (high << 4) | low;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 5XY2 - Stores VX to VY in memory starting at i, i is unchanged
+            Instruction::StoreRange { x, y } => {
+                let count = (x as i8 - y as i8).unsigned_abs() as u16 + 1;
+                self.check_range(self.i, count)?;
+                for offset in 0..count {
+                    let reg = if x <= y {
+                        x as u16 + offset
+                    } else {
+                        x as u16 - offset
+                    };
+                    self.memory[(self.i + offset) as usize] = self.v[reg as usize];
                 }
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 5XY3 - Fills VX to VY from memory starting at i, i is unchanged
+            Instruction::LoadRange { x, y } => {
+                let count = (x as i8 - y as i8).unsigned_abs() as u16 + 1;
+                self.check_range(self.i, count)?;
+                for offset in 0..count {
+                    let reg = if x <= y {
+                        x as u16 + offset
+                    } else {
+                        x as u16 - offset
+                    };
+                    self.v[reg as usize] = self.memory[(self.i + offset) as usize];
+                }
+                self.pc = self.pc.wrapping_add(2);
             }
             // 6XNN - Sets VX to NN
-            0x6000 => {
-                let x = (self.opcode & 0x0F00) >> 8;
-                self.v[x as usize] = (self.opcode & 0x00FF) as u8;
-                self.pc += 2;
+            Instruction::LoadImm { x, nn } => {
+                self.v[x as usize] = nn;
+                self.pc = self.pc.wrapping_add(2);
             }
             // 7XNN - Adds NN to VX
-            0x7000 => {
-                let x = (self.opcode & 0x0F00) >> 8;
-                self.v[x as usize] = self.v[x as usize].wrapping_add((self.opcode & 0x00FF) as u8);
-                //self.v[x as usize] += (self.opcode & 0x00FF) as u8;
-                self.pc += 2;
-            }
-            // 8XY_
-            0x8000 => match self.opcode & 0x000F {
-                // 8XY0 - Sets VX to the value of VY
-                0x0000 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    let y = (self.opcode & 0x00F0) >> 4;
-                    self.v[x as usize] = self.v[y as usize];
-                    self.pc += 2;
-                }
-                // 8XY1 - Sets VX to (VX OR VY)
-                0x0001 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    let y = (self.opcode & 0x00F0) >> 4;
-                    self.v[x as usize] |= self.v[y as usize];
-                    self.pc += 2;
+            Instruction::AddImm { x, nn } => {
+                self.v[x as usize] = self.v[x as usize].wrapping_add(nn);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 8XY0 - Sets VX to the value of VY
+            Instruction::LoadReg { x, y } => {
+                self.v[x as usize] = self.v[y as usize];
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 8XY1 - Sets VX to (VX OR VY)
+            Instruction::Or { x, y } => {
+                self.v[x as usize] |= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
                 }
-                // 8XY2 - Sets VX to (VX AND VY)
-                0x0002 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    let y = (self.opcode & 0x00F0) >> 4;
-                    self.v[x as usize] &= self.v[y as usize];
-                    self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 8XY2 - Sets VX to (VX AND VY)
+            Instruction::And { x, y } => {
+                self.v[x as usize] &= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
                 }
-                // 8XY3 = Sets VX to (VX XOR VY)
-                0x0003 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    let y = (self.opcode & 0x00F0) >> 4;
-                    self.v[x as usize] ^= self.v[y as usize];
-                    self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 8XY3 = Sets VX to (VX XOR VY)
+            Instruction::Xor { x, y } => {
+                self.v[x as usize] ^= self.v[y as usize];
+                if self.quirks.vf_reset {
+                    self.v[0xF] = 0;
                 }
-                /*
-                    8XY4 - Adds VY to VX. VF is set to 1 when there is a carry,
-                    and 0 when there isn't
-                */
-                0x0004 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    let y = (self.opcode & 0x00F0) >> 4;
-
-                    let n = self.v[x as usize].wrapping_add(self.v[y as usize]);
-                    self.v[x as usize] = n;
-
-                    if self.v[y as usize] > self.v[x as usize] {
-                        self.v[0xF] = 1 // carry
-                    } else {
-                        self.v[0xF] = 0 // no carry
-                    }
+                self.pc = self.pc.wrapping_add(2);
+            }
+            /*
+                8XY4 - Adds VY to VX. VF is set to 1 when there is a carry,
+                and 0 when there isn't
+            */
+            Instruction::AddReg { x, y } => {
+                let n = self.v[x as usize].wrapping_add(self.v[y as usize]);
+                self.v[x as usize] = n;
 
-                    self.pc += 2;
+                if self.v[y as usize] > self.v[x as usize] {
+                    self.v[0xF] = 1 // carry
+                } else {
+                    self.v[0xF] = 0 // no carry
                 }
-                /*
-                    8XY5 - VY is subtracted from VX. VF is set to 0 when there is a borrow,
-                    and 1 when there isn't
-                */
-                0x0005 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    let y = (self.opcode & 0x00F0) >> 4;
-
-                    if self.v[y as usize] > self.v[x as usize] {
-                        self.v[0xF] = 0 // borrow
-                    } else {
-                        self.v[0xF] = 1 // no borrow
-                    }
-
-                    let n = self.v[x as usize].wrapping_sub(self.v[y as usize]);
-                    self.v[x as usize] = n;
 
-                    self.pc += 2;
-                }
-                /*
-                    8XY6 - Shifts VX right by one. VF is set to the value of
-                    the least significant bit of VX before the shift
-                */
-                0x0006 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    self.v[0xF] = self.v[x as usize] & 0x1;
-                    self.v[x as usize] >>= 1;
-
-                    self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            /*
+                8XY5 - VY is subtracted from VX. VF is set to 0 when there is a borrow,
+                and 1 when there isn't
+            */
+            Instruction::SubReg { x, y } => {
+                if self.v[y as usize] > self.v[x as usize] {
+                    self.v[0xF] = 0 // borrow
+                } else {
+                    self.v[0xF] = 1 // no borrow
                 }
-                /*
-                    8XY7 - Sets VX to VY minus VX. VF is set to 0 when there's
-                    a borrow, and 1 when there isn't.
-                */
-                0x0007 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    let y = (self.opcode & 0x00F0) >> 4;
-
-                    if self.v[x as usize] > self.v[y as usize] {
-                        self.v[0xF] = 0 // borrow
-                    } else {
-                        self.v[0xF] = 1 // no borrow
-                    }
 
-                    self.v[x as usize] = self.v[y as usize] - self.v[x as usize];
+                let n = self.v[x as usize].wrapping_sub(self.v[y as usize]);
+                self.v[x as usize] = n;
 
-                    self.pc += 2;
-                }
-                /*
-                    8XYE - Shifts VX left by one. VF is set to the value of the
-                    most significant bit of VX before the shift.
-                */
-                0x000E => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    self.v[0xF] = self.v[x as usize] >> 7;
-                    self.v[x as usize] <<= 1;
-
-                    self.pc += 2;
-                }
-                _ => println!("Unknown opcode"),
-            },
-            // 9XY0 - Skips the next instruction if VX != VY
-            0x9000 => {
-                let x = (self.opcode & 0x0F00) >> 8;
-                let vx = self.v[x as usize];
-                let y = (self.opcode & 0x00F0) >> 4;
-                let vy = self.v[y as usize];
+                self.pc = self.pc.wrapping_add(2);
+            }
+            /*
+                8XY6 - Shifts VX right by one. VF is set to the value of
+                the least significant bit of VX before the shift.
+                With the shift_uses_vy quirk VY is shifted into VX instead.
+            */
+            Instruction::ShiftRight { x, y } => {
+                let source = if self.quirks.shift_uses_vy { y } else { x };
+                let value = self.v[source as usize];
+                self.v[x as usize] = value >> 1;
+                self.v[0xF] = value & 0x1;
 
-                if vx != vy {
-                    self.pc += 4;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            /*
+                8XY7 - Sets VX to VY minus VX. VF is set to 0 when there's
+                a borrow, and 1 when there isn't.
+            */
+            Instruction::SubNeg { x, y } => {
+                if self.v[x as usize] > self.v[y as usize] {
+                    self.v[0xF] = 0 // borrow
                 } else {
-                    self.pc += 2;
+                    self.v[0xF] = 1 // no borrow
                 }
+
+                self.v[x as usize] = self.v[y as usize].wrapping_sub(self.v[x as usize]);
+
+                self.pc = self.pc.wrapping_add(2);
+            }
+            /*
+                8XYE - Shifts VX left by one. VF is set to the value of the
+                most significant bit of VX before the shift.
+                With the shift_uses_vy quirk VY is shifted into VX instead.
+            */
+            Instruction::ShiftLeft { x, y } => {
+                let source = if self.quirks.shift_uses_vy { y } else { x };
+                let value = self.v[source as usize];
+                self.v[x as usize] = value << 1;
+                self.v[0xF] = value >> 7;
+
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // 9XY0 - Skips the next instruction if VX != VY
+            Instruction::SkipNeReg { x, y } => {
+                let skip = self.v[x as usize] != self.v[y as usize];
+                self.skip_if(skip);
             }
             // ANNN - Sets i to address NNN
-            0xA000 => {
-                self.i = self.opcode & 0x0FFF;
-                self.pc += 2;
+            Instruction::LoadI { nnn } => {
+                self.i = nnn;
+                self.pc = self.pc.wrapping_add(2);
             }
-            // BNNN - Jumps to the address NNN + V0
-            0xB000 => {
-                self.pc = (self.opcode & 0x0FFF) + self.v[0] as u16;
+            // BNNN - Jumps to the address NNN + V0, or NNN + VX with jump_uses_vx
+            Instruction::JumpOffset { nnn } => {
+                let x = if self.quirks.jump_uses_vx {
+                    (nnn >> 8) as usize
+                } else {
+                    0
+                };
+                self.pc = nnn + self.v[x] as u16;
             }
-            // CXNN - Sets VX to a random number, masked by NN
-            0xC000 => {
-                // pretty sure i can just gen a random u8 instead of doing the masking
-                let rn: u8 = rand::random();
-                //let mod_number = (0xFF as u16).wrapping_add(1);
-                let mut masked_rn: u8 = (rn) & (self.opcode & 0x0FF) as u8;
-                let vx_index = ((self.opcode & 0x0F00) >> 8) as usize;
-                self.v[vx_index] = masked_rn;
-                self.pc += 2;
-            }
-            /* 
-                    DXYN: Draws a sprite at coordinate (VX, VY) that has a width of 8 pixels
-                    and a height of N pixels.
-                    Each row of 8 pixels is read as bit-coded starting from memory location i.
-                    I value doesn't change after the execution of this instruction.
-                    VF is set to 1 if any screen pixels are flipped from set to unset when
-                    the sprire is drawn, and to 0 if that doesn't happen.
-                */
-            0xD000 => {
-                let vx_index = ((self.opcode & 0x0F00) >> 8) as usize;
-                let x = self.v[vx_index] as u16;
-                let vy_index = ((self.opcode & 0x00F0) >> 4) as usize;
-                let y = self.v[vy_index] as u16;
-                let height = self.opcode & 0x000F;
-                let mut pixel: u16;
-
-                self.v[0xF] = 0;
-                for yline in 0..height {
-                    pixel = self.memory[(self.i + yline) as usize] as u16;
-                    for xline in 0..8 {
-                        if (pixel & (0x80 >> xline)) != 0 {
-                            let index = (x + xline + ((y + yline) * 64)) % 2048;
-                            if self.gfx[index as usize] == 1 {
-                                self.v[0xF] = 1;
-                            }
-                            self.gfx[index as usize] ^= 1;
+            /*
+                BXY0 - Sets the foreground colour of a block of 8x4 pixel zones
+                to VY. The low nibbles of VX and VX+1 are the first column and
+                row of the block, the high nibbles how many more to cover.
+            */
+            Instruction::ColorZones { x, y } => {
+                let horizontal = self.v[x as usize];
+                let vertical = self.v[(x as usize + 1) & 0xF];
+                let color = self.v[y as usize] & 0x7;
+
+                let first_row = (vertical & 0xF) as usize;
+                let first_column = (horizontal & 0xF) as usize;
+                for row in first_row..=first_row + (vertical >> 4) as usize {
+                    for column in first_column..=first_column + (horizontal >> 4) as usize {
+                        for line in 0..4 {
+                            self.set_zone_color(column, row * 4 + line, color);
                         }
                     }
                 }
+                self.draw_flag = true;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            /*
+                BXYN - Sets the foreground colour of N rows to VY, starting at
+                the zone holding pixel (VX, VX+1)
+            */
+            Instruction::ColorRows { x, y, n } => {
+                let column = self.v[x as usize] as usize / COLOR_ZONE_WIDTH;
+                let first_row = self.v[(x as usize + 1) & 0xF] as usize;
+                let color = self.v[y as usize] & 0x7;
 
+                for row in first_row..first_row + n as usize {
+                    self.set_zone_color(column, row, color);
+                }
                 self.draw_flag = true;
-                self.pc += 2;
-            }
-            // EX__
-            0xE000 => match self.opcode & 0x00FF {
-                // EX9E - Skips the next instruction if the key stored in VX is pressed
-                0x009E => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    let vx = self.v[x as usize];
-                    let key = self.key[vx as usize];
-
-                    if key != 0 {
-                        self.pc += 4;
-                    } else {
-                        self.pc += 2;
-                    }
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // CXNN - Sets VX to a random number, masked by NN
+            Instruction::Random { x, nn } => {
+                self.v[x as usize] = self.rng.next_byte() & nn;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            /*
+                DXYN: Draws a sprite at coordinate (VX, VY) that has a width of 8 pixels
+                and a height of N pixels.
+                Each row of 8 pixels is read as bit-coded starting from memory location i.
+                I value doesn't change after the execution of this instruction.
+                VF is set to 1 if any screen pixels are flipped from set to unset when
+                the sprire is drawn, and to 0 if that doesn't happen.
+                The starting coordinate always wraps around the screen, pixels that run
+                off an edge are clipped or wrapped depending on the clip_x/clip_y quirks.
+                With display_wait only one sprite is drawn per frame, like on the VIP
+                where the interpreter waited for the vertical blank interrupt.
+            */
+            Instruction::Draw { x, y, n } => {
+                if self.quirks.display_wait && !self.vblank_ready {
+                    return Ok(StepOutcome::WaitingForVblank);
                 }
-                //EXA1 - Skips the next instructions if they key stored in VS isn't pressed
-                0x00A1 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    let vx = self.v[x as usize];
-                    let key = self.key[vx as usize];
-
-                    if key == 0 {
-                        self.pc += 4;
-                    } else {
-                        self.pc += 2;
+
+                let x = self.v[x as usize] as usize % self.display_width();
+                let y = self.v[y as usize] as usize % self.display_height();
+
+                // SUPER-CHIP draws a 16x16 sprite, two bytes per row, for DXY0
+                let (width, height) = if n == 0 && self.platform.has_super_chip() {
+                    (16, 16)
+                } else {
+                    (8, n as usize)
+                };
+
+                // XO-CHIP stores one sprite per selected bitplane back to back
+                let sprite_len = height * width / 8;
+                let planes = self.planes.count_ones() as usize;
+                self.check_range(self.i, (sprite_len * planes) as u16)?;
+                self.vblank_ready = false;
+
+                let mut addr = self.i as usize;
+                let mut collision = false;
+                for bit in [1, 2].iter() {
+                    if self.planes & bit != 0 {
+                        collision |= self.draw_plane(addr, x, y, width, height, *bit);
+                        addr += sprite_len;
                     }
                 }
-                _ => println!("Unknown opcode"),
-            },
-            // FX__
-            0xF000 => match self.opcode & 0x00FF {
-                // FX0A - Sets VX to the value of the delay timer
-                0x0007 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    self.v[x as usize] = self.delay_timer;
-                    self.pc += 2;
+                self.v[0xF] = collision as u8;
+
+                self.draw_flag = true;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // EX9E - Skips the next instruction if the key stored in VX is pressed
+            Instruction::SkipKeyPressed { x } => {
+                let skip = self.key[(self.v[x as usize] & 0xF) as usize] != 0;
+                self.skip_if(skip);
+            }
+            // EXA1 - Skips the next instructions if they key stored in VX isn't pressed
+            Instruction::SkipKeyNotPressed { x } => {
+                let skip = self.key[(self.v[x as usize] & 0xF) as usize] == 0;
+                self.skip_if(skip);
+            }
+            // EXF2 - Skips the next instruction if the key in VX is pressed on the second keypad
+            Instruction::SkipKey2Pressed { x } => {
+                let skip = self.key2[(self.v[x as usize] & 0xF) as usize] != 0;
+                self.skip_if(skip);
+            }
+            // EXF5 - Skips the next instruction if the key in VX isn't pressed on the second keypad
+            Instruction::SkipKey2NotPressed { x } => {
+                let skip = self.key2[(self.v[x as usize] & 0xF) as usize] == 0;
+                self.skip_if(skip);
+            }
+            // F000 NNNN - Sets i to the 16 bit address in the following word
+            Instruction::LoadILong => {
+                let next = self.pc as usize + 2;
+                if next + 1 >= self.memory.len() {
+                    return Err(Chip8Error::PcOutOfBounds {
+                        pc: self.pc.wrapping_add(2),
+                    });
                 }
-                // FX0A - A key press is awaited, and then stored in VX
-                0x000A => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    let mut key_pressed = false;
-
-                    for i in 0..16 {
-                        if self.key[i] != 0 {
-                            self.v[x as usize] = i as u8;
-                            key_pressed = true;
-                        }
-                    }
+                self.i = (self.memory[next] as u16) << 8 | self.memory[next + 1] as u16;
+                self.pc = self.pc.wrapping_add(4);
+            }
+            // FN01 - Selects the bitplanes (0-3) drawing and clearing affect
+            Instruction::SelectPlanes { n } => {
+                self.planes = n & 0x3;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // F002 - Loads the 16 byte audio pattern from memory at i
+            Instruction::LoadAudio => {
+                self.check_range(self.i, 16)?;
+                let mut pattern = [0; 16];
+                pattern.copy_from_slice(&self.memory[self.i as usize..self.i as usize + 16]);
+                self.audio_pattern = Some(pattern);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // FX07 - Sets VX to the value of the delay timer
+            Instruction::LoadDelay { x } => {
+                self.v[x as usize] = self.delay_timer;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // FX0A - A key press is awaited, and then stored in VX
+            Instruction::WaitKey { x } => {
+                let mut key_pressed = false;
 
-                    // only increment pc if we press a key
-                    // this is different from how the C++ program does it
-                    if key_pressed {
-                        self.pc += 2;
+                for i in 0..16 {
+                    if self.key[i] != 0 {
+                        self.v[x as usize] = i as u8;
+                        key_pressed = true;
                     }
                 }
-                // FX15 - Sets the delay timer to VX
-                0x0015 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    self.delay_timer = self.v[x as usize];
-                    self.pc += 2;
+
+                // only increment pc if we press a key
+                // this is different from how the C++ program does it
+                if !key_pressed {
+                    return Ok(StepOutcome::WaitingForKey);
                 }
-                // FX18 - Sets the sound timer to VX
-                0x0018 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    self.sound_timer = self.v[x as usize];
-                    self.pc += 2;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // FX15 - Sets the delay timer to VX
+            Instruction::SetDelay { x } => {
+                self.delay_timer = self.v[x as usize];
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // FX18 - Sets the sound timer to VX
+            Instruction::SetSound { x } => {
+                self.sound_timer = self.v[x as usize];
+                self.pc = self.pc.wrapping_add(2);
+            }
+            /*
+                FX1E - Adds VX to i. On 4 KB machines VF is set to 1 when i
+                goes past the end of memory and 0 otherwise, XO-CHIP has the
+                whole 64 KB to address and leaves VF alone.
+            */
+            Instruction::AddI { x } => {
+                let n = self.i.wrapping_add(self.v[x as usize] as u16);
+
+                if self.memory.len() <= MEMORY_SIZE {
+                    self.v[0xF] = (n > 0xFFF) as u8;
                 }
-                // FX1E - Adds VX to i
-                0x001E => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    let n = self.i.wrapping_add(self.v[x as usize] as u16);
 
-                    if n > 0xFFF {
-                        self.v[0xF] = 1;
-                    } else {
-                        self.v[0xF] = 0;
-                    }
+                self.i = n;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            /*
+                FX29 - Sets i to the location of the sprite for the character in VX.
+                Characters 0-F (in hex) are represented by a 4x5 font
+            */
+            Instruction::LoadFont { x } => {
+                self.i = (self.v[x as usize] & 0xF) as u16 * 0x5;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // FX30 - Sets i to the 8x10 big font sprite for the digit in VX
+            Instruction::LoadBigFont { x } => {
+                self.i = (BIG_FONT_ADDRESS + (self.v[x as usize] & 0xF) as usize * 10) as u16;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // FX3A - Sets the audio pattern playback pitch to VX
+            Instruction::SetPitch { x } => {
+                self.pitch = self.v[x as usize];
+                self.pc = self.pc.wrapping_add(2);
+            }
+            /*
+                FX33 - Stores the binary-coded decimal representation of VX at the
+                address i, i + 1, and i + 2
+            */
+            Instruction::StoreBcd { x } => {
+                let vx = self.v[x as usize];
+                self.check_range(self.i, 3)?;
+                self.memory[self.i as usize] = vx / 100;
+                self.memory[self.i as usize + 1] = (vx / 10) % 10;
+                self.memory[self.i as usize + 2] = vx % 10;
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // FX55 - Stores V0 to VX in memory starting at address i
+            Instruction::StoreRegs { x } => {
+                let x = x as u16;
+                self.check_range(self.i, x + 1)?;
 
-                    self.i += self.v[x as usize] as u16;
-                    self.pc += 2;
+                for index in 0..=x {
+                    self.memory[(self.i + index) as usize] = self.v[index as usize];
                 }
-                /* 
-                    FX29 - Sets i to the location of the sprite for the character in VX.
-                    Characters 0-F (in hex) are represented by a 4x5 font
-                */
-                0x0029 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    self.i = (self.v[x as usize] * 0x5) as u16;
-                    self.pc += 2;
+
+                // On original interpreter, when operation is done i = i + x + 1
+                self.i = self
+                    .i
+                    .wrapping_add(self.quirks.load_store_increments_i.amount(x));
+
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // FX65 - Fills V0 to VX with values from memory starting at address i
+            Instruction::LoadRegs { x } => {
+                let x = x as u16;
+                self.check_range(self.i, x + 1)?;
+
+                for index in 0..=x {
+                    self.v[index as usize] = self.memory[(self.i + index) as usize];
                 }
-                /* 
-                    FX33 - Stores the binary-coded decimal representation of VX at the 
-                    address i, i + 1, and i + 2
-                */
-                0x0033 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
-                    self.memory[self.i as usize] = self.v[x as usize] / 100;
-                    self.memory[self.i as usize + 1] = (self.v[x as usize] / 10) % 10;
-                    self.memory[self.i as usize + 2] = (self.v[x as usize] % 100) % 10;
-                    self.pc += 2;
+
+                // On original interpreter, when operation is done i = i + x + 1
+                self.i = self
+                    .i
+                    .wrapping_add(self.quirks.load_store_increments_i.amount(x));
+
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // FX75 - Stores V0 to VX in the RPL user flags
+            Instruction::StoreFlags { x } => {
+                let count = x as usize + 1;
+                self.flags[..count].copy_from_slice(&self.v[..count]);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // FX85 - Fills V0 to VX from the RPL user flags
+            Instruction::LoadFlags { x } => {
+                let count = x as usize + 1;
+                self.v[..count].copy_from_slice(&self.flags[..count]);
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // FXF8 - Writes VX to the output port
+            Instruction::OutputPort { x } => {
+                self.output_port = self.v[x as usize];
+                self.pc = self.pc.wrapping_add(2);
+            }
+            // FXFB - Waits for a byte on the input port and stores it in VX
+            Instruction::InputPort { x } => match self.input_port.take() {
+                Some(value) => {
+                    self.v[x as usize] = value;
+                    self.pc = self.pc.wrapping_add(2);
                 }
-                // FX55 - Stores V0 to VX in memory starting at address i
-                0x0055 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
+                None => return Ok(StepOutcome::WaitingForInput),
+            },
+        }
 
-                    for index in 0..x {
-                        self.memory[(self.i + index) as usize] = self.v[index as usize];
-                    }
+        Ok(StepOutcome::Executed(instruction))
+    }
 
-                    // On original interpreter, when operation is done i = i + x + 1
-                    self.i += x + 1;
+    // Clears the selected bitplanes
+    fn clear_display(&mut self) {
+        let keep = !self.planes;
+        for pixel in self.gfx.iter_mut() {
+            *pixel &= keep;
+        }
+    }
+
+    // Moves the selected bitplanes by (dx, dy) pixels, filling the uncovered
+    // area with blank pixels
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let width = self.display_width() as isize;
+        let height = self.display_height() as isize;
+        let planes = self.planes;
+        let source = self.gfx.clone();
 
-                    self.pc += 2;
+        for y in 0..height {
+            for x in 0..width {
+                let (sx, sy) = (x - dx, y - dy);
+                let moved = if sx >= 0 && sx < width && sy >= 0 && sy < height {
+                    source[(sx + sy * width) as usize] & planes
+                } else {
+                    0
+                };
+                let pixel = &mut self.gfx[(x + y * width) as usize];
+                *pixel = (*pixel & !planes) | moved;
+            }
+        }
+        self.draw_flag = true;
+    }
+
+    // XORs one bitplane of a sprite onto the display, returns whether any
+    // lit pixel was turned off
+    fn draw_plane(
+        &mut self,
+        addr: usize,
+        x: usize,
+        y: usize,
+        width: usize,
+        height: usize,
+        bit: u8,
+    ) -> bool {
+        let screen_width = self.display_width();
+        let screen_height = self.display_height();
+        let row_bytes = width / 8;
+        let mut collision = false;
+
+        for yline in 0..height {
+            let mut py = y + yline;
+            if py >= screen_height {
+                if self.quirks.clip_y {
+                    break;
                 }
-                // does things that are important i think???
-                0x0065 => {
-                    let x = (self.opcode & 0x0F00) >> 8;
+                py %= screen_height;
+            }
 
-                    for index in 0..=x {
-                        self.v[index as usize] = self.memory[(self.i + index) as usize];
-                    }
+            let row_addr = addr + yline * row_bytes;
+            let mut row: u16 = 0;
+            for byte in &self.memory[row_addr..row_addr + row_bytes] {
+                row = (row << 8) | *byte as u16;
+            }
 
-                    // On original interpreter, when operation is done i = i + x + 1
-                    self.i += x + 1;
+            for xline in 0..width {
+                if (row & (1 << (width - 1 - xline))) == 0 {
+                    continue;
+                }
 
-                    self.pc += 2;
+                let mut px = x + xline;
+                if px >= screen_width {
+                    if self.quirks.clip_x {
+                        break;
+                    }
+                    px %= screen_width;
                 }
-                _ => println!("Unknown opcode"),
-            },
-            _ => println!("Unimplemented opcode {:X}", self.opcode & 0xF000),
+
+                let index = px + py * screen_width;
+                if self.gfx[index] & bit != 0 {
+                    collision = true;
+                }
+                self.gfx[index] ^= bit;
+            }
         }
 
-        // Update timers
-        if self.delay_timer > 0 {
-            self.delay_timer -= 1;
+        collision
+    }
+
+    // Sets the CHIP-8X colour of one zone, coordinates wrap around the screen
+    fn set_zone_color(&mut self, column: usize, row: usize, color: u8) {
+        let index = column % COLOR_ZONE_COLUMNS + (row % COLOR_ZONE_ROWS) * COLOR_ZONE_COLUMNS;
+        self.zone_colors[index] = color;
+    }
+
+    // Switches between the 64x32 and 128x64 display modes, both start blank
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.gfx = vec![0; self.display_width() * self.display_height()];
+        self.draw_flag = true;
+    }
+
+    // Fails if any of the `len` bytes starting at `start` lie outside of memory
+    fn check_range(&self, start: u16, len: u16) -> Result<(), Chip8Error> {
+        let end = start as u32 + len as u32;
+        if end > self.memory.len() as u32 {
+            return Err(Chip8Error::MemoryOutOfBounds {
+                addr: self.pc,
+                target: end - 1,
+            });
         }
+        Ok(())
+    }
 
-        if self.sound_timer > 0 {
-            if self.sound_timer == 1 {
-                // Do sound things
+    // Skips the next instruction when the condition holds. On XO-CHIP the
+    // four byte F000 NNNN is skipped as a whole.
+    fn skip_if(&mut self, condition: bool) {
+        if !condition {
+            self.pc = self.pc.wrapping_add(2);
+            return;
+        }
+
+        let next = self.pc as usize + 2;
+        let long = self.platform.has_xo_chip()
+            && next + 1 < self.memory.len()
+            && self.memory[next] == 0xF0
+            && self.memory[next + 1] == 0x00;
+        self.pc = self.pc.wrapping_add(if long { 6 } else { 4 });
+    }
+}
+
+impl Default for Chip8 {
+    fn default() -> Self {
+        Self::new(Quirks::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Loads a program and executes one instruction per opcode in it
+    fn run(quirks: Quirks, program: &[u8]) -> Chip8 {
+        let mut chip = Chip8::new(quirks);
+        chip.load_bytes(program).unwrap();
+        for _ in 0..program.len() / 2 {
+            match chip.emulate_cycle().unwrap() {
+                StepOutcome::Executed(_) => {}
+                outcome => panic!("unexpected {:?} at {:X}", outcome, chip.pc()),
             }
-            self.sound_timer -= 1;
         }
+        chip
+    }
+
+    fn profiles() -> [(&'static str, Quirks); 3] {
+        [
+            ("cosmac_vip", Quirks::cosmac_vip()),
+            ("chip48", Quirks::chip48()),
+            ("super_chip", Quirks::super_chip()),
+        ]
+    }
+
+    #[test]
+    fn load_store_increments_i() {
+        let expected = [0x304, 0x303, 0x300];
+        for (&(name, quirks), &i) in profiles().iter().zip(expected.iter()) {
+            // FX55 - LD [I], V3
+            let chip = run(quirks, &[0xA3, 0x00, 0x63, 0x07, 0xF3, 0x55]);
+            assert_eq!(chip.i(), i, "FX55 on {}", name);
+            assert_eq!(chip.memory()[0x303], 0x07, "FX55 on {}", name);
+
+            // FX65 - LD V3, [I]
+            let chip = run(quirks, &[0xA2, 0x00, 0xF3, 0x65]);
+            assert_eq!(chip.i(), i - 0x100, "FX65 on {}", name);
+            assert_eq!(chip.v()[..4], [0xA2, 0x00, 0xF3, 0x65], "FX65 on {}", name);
+        }
+    }
+
+    #[test]
+    fn shift_source() {
+        // V0 = 0x03, V1 = 0x81, SHR V0, V1
+        let program = [0x60, 0x03, 0x61, 0x81, 0x80, 0x16];
+        let expected = [0x40, 0x01, 0x01];
+        for (&(name, quirks), &v0) in profiles().iter().zip(expected.iter()) {
+            let chip = run(quirks, &program);
+            assert_eq!(chip.v()[0], v0, "8XY6 on {}", name);
+            assert_eq!(chip.v()[0xF], 1, "8XY6 on {}", name);
+        }
+
+        // V0 = 0x03, V1 = 0x81, SHL V0, V1
+        let program = [0x60, 0x03, 0x61, 0x81, 0x80, 0x1E];
+        let expected = [0x02, 0x06, 0x06];
+        let carry = [1, 0, 0];
+        for ((&(name, quirks), &v0), &vf) in
+            profiles().iter().zip(expected.iter()).zip(carry.iter())
+        {
+            let chip = run(quirks, &program);
+            assert_eq!(chip.v()[0], v0, "8XYE on {}", name);
+            assert_eq!(chip.v()[0xF], vf, "8XYE on {}", name);
+        }
+    }
+
+    #[test]
+    fn logic_resets_vf() {
+        // VF = 5, V0 = 0x0F, V1 = 0xF0, OR V0, V1
+        let program = [0x6F, 0x05, 0x60, 0x0F, 0x61, 0xF0, 0x80, 0x11];
+        let expected = [0, 5, 5];
+        for (&(name, quirks), &vf) in profiles().iter().zip(expected.iter()) {
+            let chip = run(quirks, &program);
+            assert_eq!(chip.v()[0], 0xFF, "8XY1 on {}", name);
+            assert_eq!(chip.v()[0xF], vf, "8XY1 on {}", name);
+        }
+    }
+
+    #[test]
+    fn jump_offset_register() {
+        // V0 = 4, V2 = 8, JP V0, 0x210
+        let program = [0x60, 0x04, 0x62, 0x08, 0xB2, 0x10];
+        let expected = [0x214, 0x218, 0x218];
+        for (&(name, quirks), &pc) in profiles().iter().zip(expected.iter()) {
+            let chip = run(quirks, &program);
+            assert_eq!(chip.pc(), pc, "BNNN on {}", name);
+        }
+    }
+
+    // Draws a sprite stored at 0x300 with its top left corner at (x, y)
+    fn draw(quirks: Quirks, x: u8, y: u8, sprite: &[u8]) -> Chip8 {
+        // LD I, 0x300; LD V0, x; LD V1, y; DRW V0, V1, N
+        let opcodes = [
+            0xA300,
+            0x6000 | u16::from(x),
+            0x6100 | u16::from(y),
+            0xD010 | sprite.len() as u16,
+        ];
+        let program: Vec<u8> = opcodes
+            .iter()
+            .flat_map(|opcode: &u16| opcode.to_be_bytes().to_vec())
+            .collect();
+
+        let mut chip = Chip8::new(quirks);
+        chip.load_bytes(&program).unwrap();
+        chip.memory[0x300..0x300 + sprite.len()].copy_from_slice(sprite);
+        for _ in 0..4 {
+            chip.emulate_cycle().unwrap();
+        }
+        chip
+    }
+
+    fn lit_columns(chip: &Chip8) -> Vec<usize> {
+        (0..chip.display_width())
+            .filter(|&x| chip.gfx[x] != 0)
+            .collect()
+    }
+
+    fn lit_rows(chip: &Chip8) -> Vec<usize> {
+        (0..chip.display_height())
+            .filter(|&y| chip.gfx[y * chip.display_width()] != 0)
+            .collect()
+    }
+
+    #[test]
+    fn sprites_clip_or_wrap_at_the_right_edge() {
+        let mut quirks = Quirks::super_chip();
+        let chip = draw(quirks, 60, 0, &[0xFF]);
+        assert_eq!(lit_columns(&chip), vec![60, 61, 62, 63]);
+
+        quirks.clip_x = false;
+        let chip = draw(quirks, 60, 0, &[0xFF]);
+        assert_eq!(lit_columns(&chip), vec![0, 1, 2, 3, 60, 61, 62, 63]);
+    }
+
+    #[test]
+    fn sprites_clip_or_wrap_at_the_bottom_edge() {
+        let mut quirks = Quirks::super_chip();
+        let chip = draw(quirks, 0, 30, &[0x80; 4]);
+        assert_eq!(lit_rows(&chip), vec![30, 31]);
+
+        quirks.clip_y = false;
+        let chip = draw(quirks, 0, 30, &[0x80; 4]);
+        assert_eq!(lit_rows(&chip), vec![0, 1, 30, 31]);
+    }
+
+    #[test]
+    fn sprite_start_wraps() {
+        // the start wraps even when clipping, 70 is column 6
+        let chip = draw(Quirks::super_chip(), 70, 0, &[0xFF]);
+        assert_eq!(lit_columns(&chip), (6..14).collect::<Vec<_>>());
+
+        let chip = draw(Quirks::super_chip(), 0, 33, &[0x80]);
+        assert_eq!(lit_rows(&chip), vec![1]);
+    }
+
+    #[test]
+    fn display_wait_draws_once_per_frame() {
+        let mut chip = Chip8::new(Quirks::cosmac_vip());
+        // DRW V0, V0, 1; DRW V0, V0, 1; JP 0x204
+        chip.load_bytes(&[0xD0, 0x01, 0xD0, 0x01, 0x12, 0x04])
+            .unwrap();
+
+        assert!(matches!(
+            chip.emulate_cycle().unwrap(),
+            StepOutcome::Executed(_)
+        ));
+        assert_eq!(chip.pc(), 0x202);
+
+        assert_eq!(chip.emulate_cycle().unwrap(), StepOutcome::WaitingForVblank);
+        assert_eq!(chip.emulate_cycle().unwrap(), StepOutcome::WaitingForVblank);
+        assert_eq!(chip.pc(), 0x202);
+
+        chip.tick_timers();
+        assert!(matches!(
+            chip.emulate_cycle().unwrap(),
+            StepOutcome::Executed(_)
+        ));
+        assert_eq!(chip.pc(), 0x204);
     }
 }
@@ -0,0 +1,219 @@
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/*
+    Settings file for the frontends, read before the command line is applied
+    so options given there win. Lines are KEY = VALUE, blank lines and lines
+    starting with # or ; are skipped. Settings after a [rom "NAME"] header
+    only apply to that ROM, matched against its file name with or without
+    the extension, ignoring case:
+
+        palette = amber
+
+        [rom "PONG2"]
+        palette = lcd
+
+    Which keys mean anything is up to the frontend reading the file.
+*/
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Config {
+    settings: Vec<Setting>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Setting {
+    pub rom: Option<String>, // Section the setting is in, None for the top
+    pub key: String,
+    pub value: String,
+    pub line: usize,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    // Line that isn't a setting, section header or comment
+    Syntax {
+        line: usize,
+        text: String,
+    },
+    // Setting whose value didn't parse, with the parser's explanation
+    InvalidValue {
+        line: usize,
+        key: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref err) => write!(f, "{}", err),
+            ConfigError::Syntax { line, ref text } => {
+                write!(
+                    f,
+                    "line {}: expected KEY = VALUE or [rom \"NAME\"], got '{}'",
+                    line, text
+                )
+            }
+            ConfigError::InvalidValue {
+                line,
+                ref key,
+                ref reason,
+            } => write!(f, "line {}: invalid {}: {}", line, key, reason),
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, ConfigError> {
+        let mut settings = Vec::new();
+        let mut rom = None;
+
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                rom = Some(section_name(line).ok_or_else(|| ConfigError::Syntax {
+                    line: line_number,
+                    text: line.to_string(),
+                })?);
+                continue;
+            }
+
+            match line.split_once('=') {
+                Some((key, value)) if !key.trim().is_empty() => settings.push(Setting {
+                    rom: rom.clone(),
+                    key: key.trim().to_lowercase(),
+                    value: value.trim().to_string(),
+                    line: line_number,
+                }),
+                _ => {
+                    return Err(ConfigError::Syntax {
+                        line: line_number,
+                        text: line.to_string(),
+                    })
+                }
+            }
+        }
+
+        Ok(Config { settings })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        Config::parse(&fs::read_to_string(path)?)
+    }
+
+    // $XDG_CONFIG_HOME/chip8_emu/config, falling back to ~/.config
+    pub fn default_path() -> Option<PathBuf> {
+        let dir = match env::var_os("XDG_CONFIG_HOME") {
+            Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => PathBuf::from(env::var_os("HOME")?).join(".config"),
+        };
+        Some(dir.join("chip8_emu").join("config"))
+    }
+
+    // Reads the file at the default path, which doesn't have to exist
+    pub fn load_default() -> Result<Self, ConfigError> {
+        match Config::default_path() {
+            Some(ref path) if path.exists() => Config::load(path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    // Every setting that applies when running the ROM at rom_path, the ones
+    // at the top before the ones in the ROM's section, each in file order.
+    // For settings that build on each other, like key bindings.
+    pub fn settings_for<'a>(&'a self, rom_path: &'a str) -> impl Iterator<Item = &'a Setting> {
+        let global = self.settings.iter().filter(|setting| setting.rom.is_none());
+        let for_rom = self.settings.iter().filter(move |setting| {
+            setting
+                .rom
+                .as_ref()
+                .is_some_and(|name| matches_rom(name, rom_path))
+        });
+        global.chain(for_rom)
+    }
+
+    // The setting for a key when running the ROM at rom_path. A setting in
+    // the ROM's section beats one at the top, and later ones beat earlier ones.
+    pub fn get(&self, rom_path: &str, key: &str) -> Option<&Setting> {
+        let for_rom = self.settings.iter().rev().find(|setting| {
+            setting.key == key
+                && setting
+                    .rom
+                    .as_ref()
+                    .is_some_and(|name| matches_rom(name, rom_path))
+        });
+        for_rom.or_else(|| {
+            self.settings
+                .iter()
+                .rev()
+                .find(|setting| setting.key == key && setting.rom.is_none())
+        })
+    }
+
+    // Parses the setting for a key, None if it isn't set
+    pub fn value<T>(&self, rom_path: &str, key: &str) -> Result<Option<T>, ConfigError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.get(rom_path, key).map(Setting::parse).transpose()
+    }
+}
+
+impl Setting {
+    pub fn parse<T>(&self) -> Result<T, ConfigError>
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.value
+            .parse()
+            .map_err(|err: T::Err| ConfigError::InvalidValue {
+                line: self.line,
+                key: self.key.clone(),
+                reason: err.to_string(),
+            })
+    }
+}
+
+// The NAME in [rom "NAME"], quotes optional
+fn section_name(line: &str) -> Option<String> {
+    let inner = line.strip_prefix('[')?.strip_suffix(']')?.trim();
+    let name = inner.strip_prefix("rom")?.trim();
+    let name = name
+        .strip_prefix('"')
+        .and_then(|name| name.strip_suffix('"'))
+        .unwrap_or(name);
+    if name.is_empty() {
+        return None;
+    }
+    Some(name.to_string())
+}
+
+fn matches_rom(name: &str, rom_path: &str) -> bool {
+    let path = Path::new(rom_path);
+    [path.file_name(), path.file_stem()]
+        .iter()
+        .filter_map(|part| part.and_then(|part| part.to_str()))
+        .any(|part| part.eq_ignore_ascii_case(name))
+}
@@ -0,0 +1,100 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use chip8::Chip8;
+
+// Text renderings of the framebuffer for golden-image tests and logs. A
+// pixel counts as lit when any XO-CHIP bitplane is set.
+
+// Output format for a framebuffer dump
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DumpFormat {
+    #[default]
+    Ascii, // '#' for lit pixels and '.' for dark ones
+    Pbm, // Plain (P1) portable bitmap, opens in most image viewers
+}
+
+pub const DUMP_FORMATS: [DumpFormat; 2] = [DumpFormat::Ascii, DumpFormat::Pbm];
+
+impl DumpFormat {
+    // Name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            DumpFormat::Ascii => "ascii",
+            DumpFormat::Pbm => "pbm",
+        }
+    }
+
+    pub fn render(self, chip: &Chip8) -> String {
+        match self {
+            DumpFormat::Ascii => ascii(chip),
+            DumpFormat::Pbm => pbm(chip),
+        }
+    }
+}
+
+impl fmt::Display for DumpFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownDumpFormat(pub String);
+
+impl fmt::Display for UnknownDumpFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = DUMP_FORMATS.iter().map(|format| format.name()).collect();
+        write!(
+            f,
+            "unknown dump format '{}', expected one of {}",
+            self.0,
+            names.join(", ")
+        )
+    }
+}
+
+impl Error for UnknownDumpFormat {}
+
+impl FromStr for DumpFormat {
+    type Err = UnknownDumpFormat;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "ascii" | "txt" => Ok(DumpFormat::Ascii),
+            "pbm" => Ok(DumpFormat::Pbm),
+            _ => Err(UnknownDumpFormat(name.to_string())),
+        }
+    }
+}
+
+// One line of '#' and '.' per display row
+pub fn ascii(chip: &Chip8) -> String {
+    let width = chip.display_width();
+    let mut out = String::with_capacity((width + 1) * chip.display_height());
+
+    for row in chip.gfx.chunks(width) {
+        for pixel in row {
+            out.push(if *pixel != 0 { '#' } else { '.' });
+        }
+        out.push('\n');
+    }
+    out
+}
+
+// Plain PBM, where 1 is black, so lit pixels come out black on white
+pub fn pbm(chip: &Chip8) -> String {
+    let width = chip.display_width();
+    let mut out = format!("P1\n{} {}\n", width, chip.display_height());
+
+    for row in chip.gfx.chunks(width) {
+        let bits: Vec<&str> = row
+            .iter()
+            .map(|pixel| if *pixel != 0 { "1" } else { "0" })
+            .collect();
+        out.push_str(&bits.join(" "));
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,122 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+// Errors raised while executing a program. Every variant carries the address
+// of the instruction that failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    // Word at addr doesn't decode to an instruction
+    UnknownOpcode { addr: u16, opcode: u16 },
+    // 2NNN with all 16 stack slots in use
+    StackOverflow { addr: u16 },
+    // 00EE with an empty stack
+    StackUnderflow { addr: u16 },
+    // Read or write of target, past the end of memory
+    MemoryOutOfBounds { addr: u16, target: u32 },
+    // Program counter ran off the end of memory
+    PcOutOfBounds { pc: u16 },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Chip8Error::UnknownOpcode { addr, opcode } => {
+                write!(f, "unknown opcode {:04X} at {:03X}", opcode, addr)
+            }
+            Chip8Error::StackOverflow { addr } => write!(f, "stack overflow at {:03X}", addr),
+            Chip8Error::StackUnderflow { addr } => write!(f, "stack underflow at {:03X}", addr),
+            Chip8Error::MemoryOutOfBounds { addr, target } => write!(
+                f,
+                "memory access out of range at {:03X} (address {:X})",
+                addr, target
+            ),
+            Chip8Error::PcOutOfBounds { pc } => {
+                write!(f, "program counter out of range ({:X})", pc)
+            }
+        }
+    }
+}
+
+impl Error for Chip8Error {}
+
+// Errors raised while loading a ROM
+#[derive(Debug)]
+pub enum LoadError {
+    // Reading the ROM failed
+    Io(io::Error),
+    // ROM is bigger than the memory between the load address and the end of RAM
+    TooLarge { size: usize, capacity: usize },
+    // Load address is past the end of memory
+    AddressOutOfRange { addr: u16, memory_size: usize },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            LoadError::Io(ref err) => write!(f, "couldn't read rom: {}", err),
+            LoadError::TooLarge { size, capacity } => write!(
+                f,
+                "rom is {} bytes but only {} bytes of memory are available",
+                size, capacity
+            ),
+            LoadError::AddressOutOfRange { addr, memory_size } => write!(
+                f,
+                "load address {:X} is past the end of the {} bytes of memory",
+                addr, memory_size
+            ),
+        }
+    }
+}
+
+impl Error for LoadError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            LoadError::Io(ref err) => Some(err),
+            LoadError::TooLarge { .. } | LoadError::AddressOutOfRange { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for LoadError {
+    fn from(err: io::Error) -> Self {
+        LoadError::Io(err)
+    }
+}
+
+// Errors raised while restoring a save state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateError {
+    // Data doesn't start with the save state magic
+    BadMagic,
+    // State was written by a different version of the format
+    UnsupportedVersion(u16),
+    // Checksum doesn't match, the state is corrupt
+    ChecksumMismatch,
+    // State ends before all fields were read
+    Truncated,
+    // State has bytes left over after the last field
+    TrailingData,
+    // State was saved on a different platform than the machine it's loaded into
+    PlatformMismatch,
+    // A field holds a value the machine can't be in, e.g. sp past the stack
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {}", version)
+            }
+            StateError::ChecksumMismatch => write!(f, "save state checksum mismatch"),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::TrailingData => write!(f, "save state has trailing data"),
+            StateError::PlatformMismatch => write!(f, "save state is for a different platform"),
+            StateError::InvalidValue(field) => write!(f, "save state has an invalid {}", field),
+        }
+    }
+}
+
+impl Error for StateError {}
@@ -0,0 +1,146 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// Ways to hide the flicker of programs that erase a sprite and draw it again
+// a little further on, which leaves it missing from some frames
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FlickerMode {
+    #[default]
+    Off, // Each frame shown as it is
+    Blend, // Each frame ORed with the one before it
+    Decay, // Pixels that turn off fade out over a few frames, like phosphor
+}
+
+pub const FLICKER_MODES: [FlickerMode; 3] =
+    [FlickerMode::Off, FlickerMode::Blend, FlickerMode::Decay];
+
+// Brightness of a pixel that is on
+pub const FULL_BRIGHTNESS: u8 = 255;
+
+// A fading pixel halves in brightness each frame and goes dark below this,
+// four frames after it was turned off
+const DECAY_CUTOFF: u8 = 16;
+
+impl FlickerMode {
+    // Name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            FlickerMode::Off => "off",
+            FlickerMode::Blend => "blend",
+            FlickerMode::Decay => "decay",
+        }
+    }
+}
+
+impl fmt::Display for FlickerMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownFlickerMode(pub String);
+
+impl fmt::Display for UnknownFlickerMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = FLICKER_MODES.iter().map(|mode| mode.name()).collect();
+        write!(
+            f,
+            "unknown flicker mode '{}', expected one of {}",
+            self.0,
+            names.join(", ")
+        )
+    }
+}
+
+impl Error for UnknownFlickerMode {}
+
+impl FromStr for FlickerMode {
+    type Err = UnknownFlickerMode;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "off" | "none" => Ok(FlickerMode::Off),
+            "blend" => Ok(FlickerMode::Blend),
+            "decay" | "phosphor" => Ok(FlickerMode::Decay),
+            _ => Err(UnknownFlickerMode(name.to_string())),
+        }
+    }
+}
+
+/*
+    Turns the framebuffer into what a frontend shows. It is fed the gfx
+    buffer once per 60 Hz frame, whether or not the program drew, and gives
+    back the gfx value to show for each pixel along with its brightness
+    from 0 to FULL_BRIGHTNESS.
+*/
+#[derive(Clone, Debug, Default)]
+pub struct FlickerFilter {
+    mode: FlickerMode,
+    pixels: Vec<u8>,   // Value shown for each pixel
+    levels: Vec<u8>,   // Brightness shown for each pixel
+    previous: Vec<u8>, // Last frame's gfx buffer
+}
+
+impl FlickerFilter {
+    pub fn new(mode: FlickerMode) -> Self {
+        FlickerFilter {
+            mode,
+            ..FlickerFilter::default()
+        }
+    }
+
+    pub fn mode(&self) -> FlickerMode {
+        self.mode
+    }
+
+    // Starts over from the next frame in the new mode
+    pub fn set_mode(&mut self, mode: FlickerMode) {
+        self.mode = mode;
+        self.pixels.clear();
+    }
+
+    // Takes the gfx buffer for a frame. Returns whether anything shown
+    // changed since the last frame, fading pixels change every frame.
+    pub fn update(&mut self, gfx: &[u8]) -> bool {
+        if gfx.len() != self.pixels.len() {
+            // the display mode changed, nothing carries over
+            self.pixels = gfx.to_vec();
+            self.levels = vec![FULL_BRIGHTNESS; gfx.len()];
+            self.previous = gfx.to_vec();
+            return true;
+        }
+
+        let mut changed = false;
+        for (index, &pixel) in gfx.iter().enumerate() {
+            let shown = self.pixels[index];
+            let (pixel, level) = match self.mode {
+                FlickerMode::Blend => (pixel | self.previous[index], FULL_BRIGHTNESS),
+                FlickerMode::Decay if pixel == 0 && shown != 0 => {
+                    let level = self.levels[index] / 2;
+                    if level < DECAY_CUTOFF {
+                        (0, FULL_BRIGHTNESS)
+                    } else {
+                        (shown, level)
+                    }
+                }
+                _ => (pixel, FULL_BRIGHTNESS),
+            };
+
+            if (pixel, level) != (shown, self.levels[index]) {
+                self.pixels[index] = pixel;
+                self.levels[index] = level;
+                changed = true;
+            }
+        }
+
+        self.previous.copy_from_slice(gfx);
+        changed
+    }
+
+    // The gfx value and brightness to show for a pixel
+    pub fn pixel(&self, index: usize) -> (u8, u8) {
+        (self.pixels[index], self.levels[index])
+    }
+}
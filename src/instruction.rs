@@ -0,0 +1,436 @@
+use std::error::Error;
+use std::fmt;
+
+use platform::Platform;
+
+// A decoded CHIP-8 instruction. X and Y are register indices, NN is a byte
+// immediate, NNN is a 12 bit address and N is a 4 bit immediate.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    ScrollDown { n: u8 },              // 00CN (SUPER-CHIP)
+    ScrollUp { n: u8 },                // 00DN (XO-CHIP)
+    ClearScreen,                       // 00E0, also 0230 (HIRES CHIP-8)
+    Return,                            // 00EE
+    ScrollRight,                       // 00FB (SUPER-CHIP)
+    ScrollLeft,                        // 00FC (SUPER-CHIP)
+    Exit,                              // 00FD (SUPER-CHIP)
+    LowRes,                            // 00FE (SUPER-CHIP)
+    HighRes,                           // 00FF (SUPER-CHIP)
+    CycleBackground,                   // 02A0 (CHIP-8X)
+    Jump { nnn: u16 },                 // 1NNN
+    Call { nnn: u16 },                 // 2NNN
+    SkipEqImm { x: u8, nn: u8 },       // 3XNN
+    SkipNeImm { x: u8, nn: u8 },       // 4XNN
+    SkipEqReg { x: u8, y: u8 },        // 5XY0
+    AddNibbles { x: u8, y: u8 },       // 5XY1 (CHIP-8X)
+    StoreRange { x: u8, y: u8 },       // 5XY2 (XO-CHIP)
+    LoadRange { x: u8, y: u8 },        // 5XY3 (XO-CHIP)
+    LoadImm { x: u8, nn: u8 },         // 6XNN
+    AddImm { x: u8, nn: u8 },          // 7XNN
+    LoadReg { x: u8, y: u8 },          // 8XY0
+    Or { x: u8, y: u8 },               // 8XY1
+    And { x: u8, y: u8 },              // 8XY2
+    Xor { x: u8, y: u8 },              // 8XY3
+    AddReg { x: u8, y: u8 },           // 8XY4
+    SubReg { x: u8, y: u8 },           // 8XY5
+    ShiftRight { x: u8, y: u8 },       // 8XY6
+    SubNeg { x: u8, y: u8 },           // 8XY7
+    ShiftLeft { x: u8, y: u8 },        // 8XYE
+    SkipNeReg { x: u8, y: u8 },        // 9XY0
+    LoadI { nnn: u16 },                // ANNN
+    JumpOffset { nnn: u16 },           // BNNN
+    ColorZones { x: u8, y: u8 },       // BXY0 (CHIP-8X)
+    ColorRows { x: u8, y: u8, n: u8 }, // BXYN (CHIP-8X)
+    Random { x: u8, nn: u8 },          // CXNN
+    Draw { x: u8, y: u8, n: u8 },      // DXYN
+    SkipKeyPressed { x: u8 },          // EX9E
+    SkipKeyNotPressed { x: u8 },       // EXA1
+    SkipKey2Pressed { x: u8 },         // EXF2 (CHIP-8X)
+    SkipKey2NotPressed { x: u8 },      // EXF5 (CHIP-8X)
+    LoadILong,                         // F000 NNNN (XO-CHIP), NNNN is the next word
+    SelectPlanes { n: u8 },            // FN01 (XO-CHIP)
+    LoadAudio,                         // F002 (XO-CHIP)
+    LoadDelay { x: u8 },               // FX07
+    WaitKey { x: u8 },                 // FX0A
+    SetDelay { x: u8 },                // FX15
+    SetSound { x: u8 },                // FX18
+    AddI { x: u8 },                    // FX1E
+    LoadFont { x: u8 },                // FX29
+    LoadBigFont { x: u8 },             // FX30 (SUPER-CHIP)
+    SetPitch { x: u8 },                // FX3A (XO-CHIP)
+    StoreBcd { x: u8 },                // FX33
+    StoreRegs { x: u8 },               // FX55
+    LoadRegs { x: u8 },                // FX65
+    StoreFlags { x: u8 },              // FX75 (SUPER-CHIP)
+    LoadFlags { x: u8 },               // FX85 (SUPER-CHIP)
+    OutputPort { x: u8 },              // FXF8 (CHIP-8X)
+    InputPort { x: u8 },               // FXFB (CHIP-8X)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    UnknownOpcode(u16),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UnknownOpcode(opcode) => write!(f, "unknown opcode {:04X}", opcode),
+        }
+    }
+}
+
+impl Error for DecodeError {}
+
+// Decodes a single CHIP-8 opcode. Every nibble that is fixed in an
+// instruction's encoding has to match, so e.g. 0x0120 is rejected rather
+// than read as 00E0.
+pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+    decode_for(Platform::Chip8, opcode)
+}
+
+// Decodes an opcode including the extensions of the given platform
+pub fn decode_for(platform: Platform, opcode: u16) -> Result<Instruction, DecodeError> {
+    let schip = platform.has_super_chip();
+    let xo = platform.has_xo_chip();
+    let hires_vip = platform == Platform::HiresChip8;
+    let c8x = platform == Platform::Chip8X;
+    let x = ((opcode & 0x0F00) >> 8) as u8;
+    let y = ((opcode & 0x00F0) >> 4) as u8;
+    let n = (opcode & 0x000F) as u8;
+    let nn = (opcode & 0x00FF) as u8;
+    let nnn = opcode & 0x0FFF;
+
+    let instruction = match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00C0..=0x00CF if schip => Instruction::ScrollDown { n },
+            0x00D0..=0x00DF if xo => Instruction::ScrollUp { n },
+            0x00E0 => Instruction::ClearScreen,
+            0x00EE => Instruction::Return,
+            0x00FB if schip => Instruction::ScrollRight,
+            0x00FC if schip => Instruction::ScrollLeft,
+            0x00FD if schip => Instruction::Exit,
+            0x00FE if schip => Instruction::LowRes,
+            0x00FF if schip => Instruction::HighRes,
+            0x0230 if hires_vip => Instruction::ClearScreen,
+            0x02A0 if c8x => Instruction::CycleBackground,
+            _ => return Err(DecodeError::UnknownOpcode(opcode)),
+        },
+        0x1000 => Instruction::Jump { nnn },
+        0x2000 => Instruction::Call { nnn },
+        0x3000 => Instruction::SkipEqImm { x, nn },
+        0x4000 => Instruction::SkipNeImm { x, nn },
+        0x5000 => match n {
+            0x0 => Instruction::SkipEqReg { x, y },
+            0x1 if c8x => Instruction::AddNibbles { x, y },
+            0x2 if xo => Instruction::StoreRange { x, y },
+            0x3 if xo => Instruction::LoadRange { x, y },
+            _ => return Err(DecodeError::UnknownOpcode(opcode)),
+        },
+        0x6000 => Instruction::LoadImm { x, nn },
+        0x7000 => Instruction::AddImm { x, nn },
+        0x8000 => match n {
+            0x0 => Instruction::LoadReg { x, y },
+            0x1 => Instruction::Or { x, y },
+            0x2 => Instruction::And { x, y },
+            0x3 => Instruction::Xor { x, y },
+            0x4 => Instruction::AddReg { x, y },
+            0x5 => Instruction::SubReg { x, y },
+            0x6 => Instruction::ShiftRight { x, y },
+            0x7 => Instruction::SubNeg { x, y },
+            0xE => Instruction::ShiftLeft { x, y },
+            _ => return Err(DecodeError::UnknownOpcode(opcode)),
+        },
+        0x9000 if n == 0 => Instruction::SkipNeReg { x, y },
+        0xA000 => Instruction::LoadI { nnn },
+        // CHIP-8X replaced the jump with the colour instructions
+        0xB000 if c8x && n == 0 => Instruction::ColorZones { x, y },
+        0xB000 if c8x => Instruction::ColorRows { x, y, n },
+        0xB000 => Instruction::JumpOffset { nnn },
+        0xC000 => Instruction::Random { x, nn },
+        0xD000 => Instruction::Draw { x, y, n },
+        0xE000 => match nn {
+            0x9E => Instruction::SkipKeyPressed { x },
+            0xA1 => Instruction::SkipKeyNotPressed { x },
+            0xF2 if c8x => Instruction::SkipKey2Pressed { x },
+            0xF5 if c8x => Instruction::SkipKey2NotPressed { x },
+            _ => return Err(DecodeError::UnknownOpcode(opcode)),
+        },
+        0xF000 => match nn {
+            0x00 if xo && x == 0 => Instruction::LoadILong,
+            0x01 if xo => Instruction::SelectPlanes { n: x },
+            0x02 if xo && x == 0 => Instruction::LoadAudio,
+            0x07 => Instruction::LoadDelay { x },
+            0x0A => Instruction::WaitKey { x },
+            0x15 => Instruction::SetDelay { x },
+            0x18 => Instruction::SetSound { x },
+            0x1E => Instruction::AddI { x },
+            0x29 => Instruction::LoadFont { x },
+            0x30 if schip => Instruction::LoadBigFont { x },
+            0x3A if xo => Instruction::SetPitch { x },
+            0x33 => Instruction::StoreBcd { x },
+            0x55 => Instruction::StoreRegs { x },
+            0x65 => Instruction::LoadRegs { x },
+            0x75 if schip => Instruction::StoreFlags { x },
+            0x85 if schip => Instruction::LoadFlags { x },
+            0xF8 if c8x => Instruction::OutputPort { x },
+            0xFB if c8x => Instruction::InputPort { x },
+            _ => return Err(DecodeError::UnknownOpcode(opcode)),
+        },
+        _ => return Err(DecodeError::UnknownOpcode(opcode)),
+    };
+
+    Ok(instruction)
+}
+
+// Disassembles to the usual Cowgod style mnemonics, e.g. "LD V1, 0x0A"
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::ScrollDown { n } => write!(f, "SCD {}", n),
+            Instruction::ScrollUp { n } => write!(f, "SCU {}", n),
+            Instruction::ClearScreen => write!(f, "CLS"),
+            Instruction::Return => write!(f, "RET"),
+            Instruction::ScrollRight => write!(f, "SCR"),
+            Instruction::ScrollLeft => write!(f, "SCL"),
+            Instruction::Exit => write!(f, "EXIT"),
+            Instruction::LowRes => write!(f, "LOW"),
+            Instruction::HighRes => write!(f, "HIGH"),
+            Instruction::CycleBackground => write!(f, "BGC"),
+            Instruction::Jump { nnn } => write!(f, "JP 0x{:03X}", nnn),
+            Instruction::Call { nnn } => write!(f, "CALL 0x{:03X}", nnn),
+            Instruction::SkipEqImm { x, nn } => write!(f, "SE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipNeImm { x, nn } => write!(f, "SNE V{:X}, 0x{:02X}", x, nn),
+            Instruction::SkipEqReg { x, y } => write!(f, "SE V{:X}, V{:X}", x, y),
+            Instruction::AddNibbles { x, y } => write!(f, "ADDN V{:X}, V{:X}", x, y),
+            Instruction::StoreRange { x, y } => write!(f, "LD [I], V{:X}-V{:X}", x, y),
+            Instruction::LoadRange { x, y } => write!(f, "LD V{:X}-V{:X}, [I]", x, y),
+            Instruction::LoadImm { x, nn } => write!(f, "LD V{:X}, 0x{:02X}", x, nn),
+            Instruction::AddImm { x, nn } => write!(f, "ADD V{:X}, 0x{:02X}", x, nn),
+            Instruction::LoadReg { x, y } => write!(f, "LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => write!(f, "OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => write!(f, "AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => write!(f, "XOR V{:X}, V{:X}", x, y),
+            Instruction::AddReg { x, y } => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Instruction::SubReg { x, y } => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Instruction::SubNeg { x, y } => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => write!(f, "SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipNeReg { x, y } => write!(f, "SNE V{:X}, V{:X}", x, y),
+            Instruction::LoadI { nnn } => write!(f, "LD I, 0x{:03X}", nnn),
+            Instruction::JumpOffset { nnn } => write!(f, "JP V0, 0x{:03X}", nnn),
+            Instruction::ColorZones { x, y } => write!(f, "COL V{:X}, V{:X}", x, y),
+            Instruction::ColorRows { x, y, n } => write!(f, "COL V{:X}, V{:X}, {}", x, y, n),
+            Instruction::Random { x, nn } => write!(f, "RND V{:X}, 0x{:02X}", x, nn),
+            Instruction::Draw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipKeyPressed { x } => write!(f, "SKP V{:X}", x),
+            Instruction::SkipKeyNotPressed { x } => write!(f, "SKNP V{:X}", x),
+            Instruction::SkipKey2Pressed { x } => write!(f, "SKP2 V{:X}", x),
+            Instruction::SkipKey2NotPressed { x } => write!(f, "SKNP2 V{:X}", x),
+            Instruction::LoadILong => write!(f, "LD I, LONG"),
+            Instruction::SelectPlanes { n } => write!(f, "PLANE {}", n),
+            Instruction::LoadAudio => write!(f, "AUDIO"),
+            Instruction::LoadDelay { x } => write!(f, "LD V{:X}, DT", x),
+            Instruction::WaitKey { x } => write!(f, "LD V{:X}, K", x),
+            Instruction::SetDelay { x } => write!(f, "LD DT, V{:X}", x),
+            Instruction::SetSound { x } => write!(f, "LD ST, V{:X}", x),
+            Instruction::AddI { x } => write!(f, "ADD I, V{:X}", x),
+            Instruction::LoadFont { x } => write!(f, "LD F, V{:X}", x),
+            Instruction::LoadBigFont { x } => write!(f, "LD HF, V{:X}", x),
+            Instruction::SetPitch { x } => write!(f, "PITCH V{:X}", x),
+            Instruction::StoreBcd { x } => write!(f, "LD B, V{:X}", x),
+            Instruction::StoreRegs { x } => write!(f, "LD [I], V{:X}", x),
+            Instruction::LoadRegs { x } => write!(f, "LD V{:X}, [I]", x),
+            Instruction::StoreFlags { x } => write!(f, "LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => write!(f, "LD V{:X}, R", x),
+            Instruction::OutputPort { x } => write!(f, "OUT V{:X}", x),
+            Instruction::InputPort { x } => write!(f, "IN V{:X}", x),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use platform::PLATFORMS;
+
+    use platform::Platform::{Chip8, Chip8X, HiresChip8, SuperChip, XoChip};
+
+    // Opcodes every platform decodes the same way
+    const COMMON: [(u16, Instruction); 35] = [
+        (0x00E0, Instruction::ClearScreen),
+        (0x00EE, Instruction::Return),
+        (0x1234, Instruction::Jump { nnn: 0x234 }),
+        (0x2345, Instruction::Call { nnn: 0x345 }),
+        (0x3A12, Instruction::SkipEqImm { x: 0xA, nn: 0x12 }),
+        (0x4B34, Instruction::SkipNeImm { x: 0xB, nn: 0x34 }),
+        (0x5120, Instruction::SkipEqReg { x: 1, y: 2 }),
+        (0x6C56, Instruction::LoadImm { x: 0xC, nn: 0x56 }),
+        (0x7D78, Instruction::AddImm { x: 0xD, nn: 0x78 }),
+        (0x8120, Instruction::LoadReg { x: 1, y: 2 }),
+        (0x8121, Instruction::Or { x: 1, y: 2 }),
+        (0x8122, Instruction::And { x: 1, y: 2 }),
+        (0x8123, Instruction::Xor { x: 1, y: 2 }),
+        (0x8124, Instruction::AddReg { x: 1, y: 2 }),
+        (0x8125, Instruction::SubReg { x: 1, y: 2 }),
+        (0x8126, Instruction::ShiftRight { x: 1, y: 2 }),
+        (0x8127, Instruction::SubNeg { x: 1, y: 2 }),
+        (0x812E, Instruction::ShiftLeft { x: 1, y: 2 }),
+        (0x9120, Instruction::SkipNeReg { x: 1, y: 2 }),
+        (0xA123, Instruction::LoadI { nnn: 0x123 }),
+        (0xC1FF, Instruction::Random { x: 1, nn: 0xFF }),
+        (0xD125, Instruction::Draw { x: 1, y: 2, n: 5 }),
+        (0xE39E, Instruction::SkipKeyPressed { x: 3 }),
+        (0xE3A1, Instruction::SkipKeyNotPressed { x: 3 }),
+        (0xF407, Instruction::LoadDelay { x: 4 }),
+        (0xF40A, Instruction::WaitKey { x: 4 }),
+        (0xF415, Instruction::SetDelay { x: 4 }),
+        (0xF418, Instruction::SetSound { x: 4 }),
+        (0xF41E, Instruction::AddI { x: 4 }),
+        (0xF429, Instruction::LoadFont { x: 4 }),
+        (0xF433, Instruction::StoreBcd { x: 4 }),
+        (0xF455, Instruction::StoreRegs { x: 4 }),
+        (0xF465, Instruction::LoadRegs { x: 4 }),
+        (0x1000, Instruction::Jump { nnn: 0 }),
+        (0xAFFF, Instruction::LoadI { nnn: 0xFFF }),
+    ];
+
+    // Opcodes no platform decodes, mostly near misses of real instructions
+    const INVALID: [u16; 16] = [
+        0x0000, 0x0120, 0x00E1, 0x00EF, 0x0E00, 0x5124, 0x8128, 0x812F, 0x9121, 0x912E, 0xE19F,
+        0xE1A2, 0xF1FF, 0xF106, 0xF456, 0xF466,
+    ];
+
+    #[test]
+    fn decodes_common_opcodes_on_every_platform() {
+        for &platform in PLATFORMS.iter() {
+            for &(opcode, instruction) in COMMON.iter() {
+                assert_eq!(
+                    decode_for(platform, opcode),
+                    Ok(instruction),
+                    "{:04X} on {}",
+                    opcode,
+                    platform
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_opcodes_on_every_platform() {
+        for &platform in PLATFORMS.iter() {
+            for &opcode in INVALID.iter() {
+                assert_eq!(
+                    decode_for(platform, opcode),
+                    Err(DecodeError::UnknownOpcode(opcode)),
+                    "{:04X} on {}",
+                    opcode,
+                    platform
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn decode_is_plain_chip8() {
+        assert_eq!(decode(0x00FF), Err(DecodeError::UnknownOpcode(0x00FF)));
+        assert_eq!(decode(0x0120), Err(DecodeError::UnknownOpcode(0x0120)));
+        assert_eq!(decode(0xB123), Ok(Instruction::JumpOffset { nnn: 0x123 }));
+    }
+
+    #[test]
+    fn platform_opcodes_only_decode_on_their_platforms() {
+        let table: [(u16, &[Platform], Instruction); 24] = [
+            (
+                0x00C4,
+                &[SuperChip, XoChip],
+                Instruction::ScrollDown { n: 4 },
+            ),
+            (0x00D4, &[XoChip], Instruction::ScrollUp { n: 4 }),
+            (0x00FB, &[SuperChip, XoChip], Instruction::ScrollRight),
+            (0x00FC, &[SuperChip, XoChip], Instruction::ScrollLeft),
+            (0x00FD, &[SuperChip, XoChip], Instruction::Exit),
+            (0x00FE, &[SuperChip, XoChip], Instruction::LowRes),
+            (0x00FF, &[SuperChip, XoChip], Instruction::HighRes),
+            (0x0230, &[HiresChip8], Instruction::ClearScreen),
+            (0x02A0, &[Chip8X], Instruction::CycleBackground),
+            (0x5121, &[Chip8X], Instruction::AddNibbles { x: 1, y: 2 }),
+            (0x5122, &[XoChip], Instruction::StoreRange { x: 1, y: 2 }),
+            (0x5123, &[XoChip], Instruction::LoadRange { x: 1, y: 2 }),
+            (0xE3F2, &[Chip8X], Instruction::SkipKey2Pressed { x: 3 }),
+            (0xE3F5, &[Chip8X], Instruction::SkipKey2NotPressed { x: 3 }),
+            (0xF000, &[XoChip], Instruction::LoadILong),
+            (0xF201, &[XoChip], Instruction::SelectPlanes { n: 2 }),
+            (0xF002, &[XoChip], Instruction::LoadAudio),
+            (
+                0xF430,
+                &[SuperChip, XoChip],
+                Instruction::LoadBigFont { x: 4 },
+            ),
+            (0xF43A, &[XoChip], Instruction::SetPitch { x: 4 }),
+            (
+                0xF475,
+                &[SuperChip, XoChip],
+                Instruction::StoreFlags { x: 4 },
+            ),
+            (
+                0xF485,
+                &[SuperChip, XoChip],
+                Instruction::LoadFlags { x: 4 },
+            ),
+            (0xF4F8, &[Chip8X], Instruction::OutputPort { x: 4 }),
+            (0xF4FB, &[Chip8X], Instruction::InputPort { x: 4 }),
+            (0xB120, &[Chip8X], Instruction::ColorZones { x: 1, y: 2 }),
+        ];
+
+        for &(opcode, platforms, instruction) in table.iter() {
+            for &platform in PLATFORMS.iter() {
+                let expected = if platforms.contains(&platform) {
+                    Ok(instruction)
+                } else {
+                    Err(DecodeError::UnknownOpcode(opcode))
+                };
+                // BNNN is a jump everywhere but CHIP-8X
+                let expected = match expected {
+                    Err(_) if opcode & 0xF000 == 0xB000 => Ok(Instruction::JumpOffset {
+                        nnn: opcode & 0xFFF,
+                    }),
+                    expected => expected,
+                };
+                assert_eq!(
+                    decode_for(platform, opcode),
+                    expected,
+                    "{:04X} on {}",
+                    opcode,
+                    platform
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn chip8x_replaces_bnnn_with_colour_instructions() {
+        assert_eq!(
+            decode_for(Chip8X, 0xB123),
+            Ok(Instruction::ColorRows { x: 1, y: 2, n: 3 })
+        );
+        for &platform in [Chip8, HiresChip8, SuperChip, XoChip].iter() {
+            assert_eq!(
+                decode_for(platform, 0xB123),
+                Ok(Instruction::JumpOffset { nnn: 0x123 })
+            );
+        }
+    }
+
+    #[test]
+    fn long_load_and_audio_need_a_zero_x() {
+        assert_eq!(
+            decode_for(XoChip, 0xF100),
+            Err(DecodeError::UnknownOpcode(0xF100))
+        );
+        assert_eq!(
+            decode_for(XoChip, 0xF102),
+            Err(DecodeError::UnknownOpcode(0xF102))
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// Host keys for each CHIP-8 key 0-F, by name. Names are whatever the frontend
+// understands, the SDL window takes SDL key names such as "X" or "Space",
+// by default for the key in that position on a US QWERTY keyboard, and
+// controller buttons and stick directions in a separate keymap. A CHIP-8 key
+// can have any number of host keys, pressing any of them presses it, or none.
+// The default puts the 4x4 keypad on the left of the keyboard:
+//
+//   1 2 3 C      1 2 3 4
+//   4 5 6 D  ->  Q W E R
+//   7 8 9 E      A S D F
+//   A 0 B F      Z X C V
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Keymap {
+    keys: Vec<Vec<String>>,
+}
+
+pub const DEFAULT_KEYS: [&str; 16] = [
+    "X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V",
+];
+
+// Controller inputs by the names SDL uses in controller mappings, with + or -
+// after an axis for a direction. The D-pad and left stick press 2, 4, 6 and
+// 8, which most games move with, and A and B press 5 and 0.
+pub const DEFAULT_CONTROLLER_KEYS: [&str; 16] = [
+    "b",
+    "",
+    "dpup|lefty-",
+    "",
+    "dpleft|leftx-",
+    "a",
+    "dpright|leftx+",
+    "",
+    "dpdown|lefty+",
+    "",
+    "",
+    "",
+    "",
+    "",
+    "",
+    "",
+];
+
+impl Keymap {
+    // Default keymap for game controllers
+    pub fn controller() -> Self {
+        Keymap {
+            keys: DEFAULT_CONTROLLER_KEYS
+                .iter()
+                .map(|entry| names(entry, '|'))
+                .collect(),
+        }
+    }
+
+    // Names of the host keys for a CHIP-8 key
+    pub fn keys(&self, chip8_key: usize) -> &[String] {
+        &self.keys[chip8_key]
+    }
+
+    // Replaces the host keys of one CHIP-8 key
+    pub fn bind(&mut self, binding: &Binding) {
+        self.keys[binding.key] = binding.host_keys.clone();
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Keymap {
+            keys: DEFAULT_KEYS
+                .iter()
+                .map(|key| vec![key.to_string()])
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidKeymap(pub String);
+
+impl fmt::Display for InvalidKeymap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid keymap '{}', expected 16 keys for 0-F, either as 16 characters like \
+             x123qweasdzc4rfv or as comma separated key names, with | between keys that \
+             press the same CHIP-8 key and nothing for keys left unbound",
+            self.0
+        )
+    }
+}
+
+impl Error for InvalidKeymap {}
+
+// The names in a list, leaving out empty ones
+fn names(list: &str, separator: char) -> Vec<String> {
+    list.split(separator)
+        .map(|name| name.trim())
+        .filter(|name| !name.is_empty())
+        .map(|name| name.to_string())
+        .collect()
+}
+
+// Either 16 single character keys in CHIP-8 key order, or 16 comma separated
+// entries of key names separated by |, like "X|Space,1,2,...". An empty
+// entry leaves its key unbound.
+impl FromStr for Keymap {
+    type Err = InvalidKeymap;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let keys: Vec<Vec<String>> = if text.contains(',') {
+            text.split(',').map(|entry| names(entry, '|')).collect()
+        } else {
+            text.chars().map(|key| vec![key.to_string()]).collect()
+        };
+
+        if keys.len() != 16 {
+            return Err(InvalidKeymap(text.to_string()));
+        }
+        Ok(Keymap { keys })
+    }
+}
+
+// Host keys for a single CHIP-8 key, leaving the others as they are
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Binding {
+    pub key: usize,
+    pub host_keys: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidBinding(pub String);
+
+impl fmt::Display for InvalidBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid key binding '{}', expected a hex CHIP-8 key and comma separated \
+             key names like 5=W,Up",
+            self.0
+        )
+    }
+}
+
+impl Error for InvalidBinding {}
+
+// "KEY=NAME,NAME,..." with KEY a hex digit, "KEY=" unbinds the key
+impl FromStr for Binding {
+    type Err = InvalidBinding;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let invalid = || InvalidBinding(text.to_string());
+
+        let (key, host_keys) = text.split_once('=').ok_or_else(invalid)?;
+        let key = usize::from_str_radix(key.trim(), 16)
+            .ok()
+            .filter(|key| *key < 16)
+            .ok_or_else(invalid)?;
+        Ok(Binding {
+            key,
+            host_keys: names(host_keys, ','),
+        })
+    }
+}
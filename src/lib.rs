@@ -0,0 +1,31 @@
+extern crate rand;
+
+pub mod audio;
+pub mod chip8;
+pub mod config;
+pub mod dump;
+pub mod error;
+pub mod flicker;
+pub mod instruction;
+pub mod keymap;
+pub mod options;
+pub mod palette;
+pub mod platform;
+pub mod quirks;
+pub mod rewind;
+pub mod rng;
+pub mod state;
+
+pub use audio::{Beeper, ToneSettings, Waveform};
+pub use chip8::{Chip8, RomInfo, StepOutcome};
+pub use config::{Config, ConfigError};
+pub use error::{Chip8Error, LoadError, StateError};
+pub use flicker::{FlickerFilter, FlickerMode};
+pub use instruction::{decode, decode_for, DecodeError, Instruction};
+pub use keymap::Keymap;
+pub use options::{MachineOptions, OptionError};
+pub use palette::Palette;
+pub use platform::Platform;
+pub use quirks::{IndexIncrement, Quirks};
+pub use rewind::Rewind;
+pub use rng::Rng;
@@ -1,82 +1,740 @@
+extern crate chip8_emu;
 extern crate sdl2;
 extern crate sdl2_sys;
 
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use chip8_emu::chip8;
+use chip8_emu::flicker::FLICKER_MODES;
+use chip8_emu::keymap::Binding;
+use chip8_emu::options::{option_value, positive_value, Switch, MACHINE_USAGE};
+use chip8_emu::palette::{blend, THEMES};
+use chip8_emu::rewind::DEFAULT_REWIND_FRAMES;
+use chip8_emu::{
+    Beeper, Config, ConfigError, FlickerFilter, FlickerMode, Keymap, MachineOptions, OptionError,
+    Palette, Platform, Rewind, ToneSettings,
+};
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::controller::{Axis, Button, GameController};
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Scancode, LALTMOD, LCTRLMOD, RALTMOD, RCTRLMOD};
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, TextureCreator};
+use sdl2::video::{FullscreenType, WindowContext};
+use sdl2::VideoSubsystem;
 
+use std::fs;
 use std::mem;
-use std::mem::transmute;
 use std::process;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-#[allow(unused_imports)]
 use std::env;
+use std::fmt::Display;
+use std::str::FromStr;
 
-pub mod chip8;
-
-const KEYMAP: [sdl2::keyboard::Keycode; 16] = [
-    sdl2::keyboard::Keycode::X,
-    sdl2::keyboard::Keycode::Num1,
-    sdl2::keyboard::Keycode::Num2,
-    sdl2::keyboard::Keycode::Num3,
-    sdl2::keyboard::Keycode::Q,
-    sdl2::keyboard::Keycode::W,
-    sdl2::keyboard::Keycode::E,
-    sdl2::keyboard::Keycode::A,
-    sdl2::keyboard::Keycode::S,
-    sdl2::keyboard::Keycode::D,
-    sdl2::keyboard::Keycode::Z,
-    sdl2::keyboard::Keycode::C,
-    sdl2::keyboard::Keycode::Num4,
-    sdl2::keyboard::Keycode::R,
-    sdl2::keyboard::Keycode::F,
-    sdl2::keyboard::Keycode::V,
+// ARGB colour for each VP-590 colour code used by CHIP-8X
+const VP590_COLORS: [u32; 8] = [
+    0xFF000000, // Black
+    0xFFFF0000, // Red
+    0xFF0000FF, // Blue
+    0xFFFF00FF, // Violet
+    0xFF00FF00, // Green
+    0xFFFFFF00, // Yellow
+    0xFF00FFFF, // Aqua
+    0xFFFFFFFF, // White
 ];
 
+// CHIP-8X second keypad on the numeric keypad
+const KEYMAP2: [sdl2::keyboard::Scancode; 16] = [
+    sdl2::keyboard::Scancode::Kp0,
+    sdl2::keyboard::Scancode::Kp1,
+    sdl2::keyboard::Scancode::Kp2,
+    sdl2::keyboard::Scancode::Kp3,
+    sdl2::keyboard::Scancode::Kp4,
+    sdl2::keyboard::Scancode::Kp5,
+    sdl2::keyboard::Scancode::Kp6,
+    sdl2::keyboard::Scancode::Kp7,
+    sdl2::keyboard::Scancode::Kp8,
+    sdl2::keyboard::Scancode::Kp9,
+    sdl2::keyboard::Scancode::KpDivide,
+    sdl2::keyboard::Scancode::KpMultiply,
+    sdl2::keyboard::Scancode::KpMinus,
+    sdl2::keyboard::Scancode::KpPlus,
+    sdl2::keyboard::Scancode::KpEnter,
+    sdl2::keyboard::Scancode::KpPeriod,
+];
+
+const AUDIO_SAMPLE_RATE: i32 = 44100;
+
+// Plays the core's beeper through an SDL audio device
+struct SdlBeeper(Beeper);
+
+impl AudioCallback for SdlBeeper {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        self.0.fill(out);
+    }
+}
+
+// The emulator still runs when there is no audio device, just silently
+fn open_audio(ctx: &sdl2::Sdl, tone: ToneSettings) -> Option<AudioDevice<SdlBeeper>> {
+    let desired = AudioSpecDesired {
+        freq: Some(AUDIO_SAMPLE_RATE),
+        channels: Some(1),
+        samples: None,
+    };
+
+    let device = ctx.audio().and_then(|audio| {
+        audio.open_playback(None, &desired, |spec| {
+            SdlBeeper(Beeper::new(spec.freq as u32, tone))
+        })
+    });
+    match device {
+        Ok(device) => {
+            device.resume();
+            Some(device)
+        }
+        Err(err) => {
+            eprintln!("couldn't open audio device, sound is off: {}", err);
+            None
+        }
+    }
+}
+
+fn create_texture(
+    texture_creator: &TextureCreator<WindowContext>,
+    (width, height): (usize, usize),
+) -> Texture<'_> {
+    match texture_creator.create_texture(
+        sdl2::pixels::PixelFormatEnum::ARGB8888,
+        sdl2::render::TextureAccess::Streaming,
+        width as u32,
+        height as u32,
+    ) {
+        Ok(texture) => texture,
+        Err(err) => panic!("failed to create texture: {}", err),
+    }
+}
+
+// Window pixels per CHIP-8 pixel when --scale isn't given, as large as fits
+// comfortably on the screen up to DEFAULT_SCALE
+fn fit_scale(video: &VideoSubsystem, (width, height): (usize, usize)) -> u32 {
+    match video.desktop_display_mode(0) {
+        Ok(mode) => {
+            let fit_x = mode.w.max(0) as u32 * 9 / 10 / width as u32;
+            let fit_y = mode.h.max(0) as u32 * 9 / 10 / height as u32;
+            fit_x.min(fit_y).clamp(1, DEFAULT_SCALE)
+        }
+        Err(_) => DEFAULT_SCALE,
+    }
+}
+
+// The largest area with the display's aspect ratio that fits in the window,
+// centred, leaving black bars on the other sides. With integer scaling each
+// CHIP-8 pixel covers a whole number of window pixels, unless the window is
+// too small for even one. None when the window has no area to draw in, as
+// while it is minimised on some systems.
+fn display_rect(
+    (window_width, window_height): (u32, u32),
+    (width, height): (usize, usize),
+    integer_scale: bool,
+) -> Option<Rect> {
+    if window_width == 0 || window_height == 0 {
+        return None;
+    }
+
+    let mut scale =
+        (f64::from(window_width) / width as f64).min(f64::from(window_height) / height as f64);
+    if integer_scale && scale >= 1.0 {
+        scale = scale.floor();
+    }
+
+    let rect_width = ((width as f64 * scale) as u32).clamp(1, window_width);
+    let rect_height = ((height as f64 * scale) as u32).clamp(1, window_height);
+    Some(Rect::new(
+        ((window_width - rect_width) / 2) as i32,
+        ((window_height - rect_height) / 2) as i32,
+        rect_width,
+        rect_height,
+    ))
+}
+
+// Save state slot for a number key, used with Ctrl (save) and Alt (load). By
+// position, since the number row types symbols on some layouts.
+fn state_slot(scancode: Scancode) -> Option<u32> {
+    match scancode {
+        Scancode::Num1 => Some(1),
+        Scancode::Num2 => Some(2),
+        Scancode::Num3 => Some(3),
+        Scancode::Num4 => Some(4),
+        Scancode::Num5 => Some(5),
+        Scancode::Num6 => Some(6),
+        Scancode::Num7 => Some(7),
+        Scancode::Num8 => Some(8),
+        Scancode::Num9 => Some(9),
+        _ => None,
+    }
+}
+
+// Slots are kept next to the ROM, e.g. roms/PONG2.state1
+fn state_path(rom_path: &str, slot: u32) -> String {
+    format!("{}.state{}", rom_path, slot)
+}
+
+fn save_slot(chip: &chip8::Chip8, rom_path: &str, slot: u32) {
+    let path = state_path(rom_path, slot);
+    match fs::write(&path, chip.save_state()) {
+        Ok(()) => println!("saved state to {}", path),
+        Err(err) => eprintln!("couldn't save state to {}: {}", path, err),
+    }
+}
+
+// Returns whether the state was restored
+fn load_slot(chip: &mut chip8::Chip8, rom_path: &str, slot: u32) -> bool {
+    let path = state_path(rom_path, slot);
+    let state = match fs::read(&path) {
+        Ok(state) => state,
+        Err(err) => {
+            eprintln!("couldn't read state from {}: {}", path, err);
+            return false;
+        }
+    };
+
+    match chip.load_state(&state) {
+        Ok(()) => {
+            println!("loaded state from {}", path);
+            true
+        }
+        Err(err) => {
+            eprintln!("couldn't load state from {}: {}", path, err);
+            false
+        }
+    }
+}
+
+const USAGE: &str = "usage: chip8_emu ROM [options]
+
+Plays a ROM in a window. F2 switches between the built-in palettes, F3 between the
+flicker modes and F11 toggles fullscreen.
+
+window options:
+  --config PATH              settings file (default ~/.config/chip8_emu/config)
+  --scale N                  starting window pixels per CHIP-8 pixel (default 16, or
+                             less if that doesn't fit on the screen)
+  --integer-scale on|off     only scale by whole numbers for even pixels (default off)
+  --flicker MODE             off, blend (OR each frame with the last one) or decay
+                             (pixels fade out over a few frames), default off
+  --palette NAME             built-in palette: classic, amber, green, lcd or octo
+  --palette FG,BG[,P2,BOTH]  display colours in hex, e.g. FFFFFF,000000, the last two
+                             are for the second XO-CHIP bitplane and both planes
+  --keymap KEYS              keys for CHIP-8 keys 0-F, either 16 characters like
+                             x123qweasdzc4rfv or 16 comma separated SDL key names,
+                             with | between names that press the same CHIP-8 key
+  --bind KEY=NAMES           SDL keys for one CHIP-8 key, e.g. 5=W,Up, can be given
+                             more than once
+  --key-mode MODE            scancode matches keys by where they are, named after the
+                             key there on a US QWERTY keyboard, keycode by what they
+                             type in the current layout (default scancode)
+  --pad-keymap INPUTS        controller inputs for CHIP-8 keys 0-F, 16 comma separated
+                             entries like --keymap, using SDL controller button names
+                             (a, b, dpup, start, ...) and axes with a direction
+                             (leftx-, lefty+, ...), D-pad and left stick on 2/4/6/8
+                             and A on 5 by default
+  --pad-bind KEY=INPUTS      controller inputs for one CHIP-8 key, e.g. 5=a,x, can be
+                             given more than once
+  --frequency HZ             buzzer pitch (default 440)
+  --waveform NAME            square, sine or triangle (default square)
+  --volume N                 buzzer volume from 0.0 to 1.0 (default 0.25)
+  -h, --help                 show this help";
+
+const DEFAULT_SCALE: u32 = 16;
+
+// Everything the window takes from the command line
+struct Options {
+    machine: MachineOptions,
+    config: Option<String>,
+    scale: Option<u32>,
+    integer_scale: Option<bool>,
+    palette: Option<Palette>, // Falls back to the config file, then the default
+    flicker: Option<FlickerMode>,
+    keymap: Option<Keymap>,
+    bindings: Vec<Binding>, // Applied in order on top of the keymap
+    key_mode: Option<KeyMode>,
+    pad_keymap: Option<Keymap>,
+    pad_bindings: Vec<Binding>,
+    tone: ToneSettings,
+}
+
+// Applies the keymap and bind settings named keymap_key and bind_key from the
+// config file to a keymap, then the ones from the command line on top
+fn build_keymap(
+    mut keymap: Keymap,
+    config: &Config,
+    rom_path: &str,
+    (keymap_key, bind_key): (&str, &str),
+    replacement: Option<&Keymap>,
+    bindings: &[Binding],
+) -> Result<Keymap, ConfigError> {
+    for setting in config.settings_for(rom_path) {
+        if setting.key == keymap_key {
+            keymap = setting.parse()?;
+        } else if setting.key == bind_key {
+            keymap.bind(&setting.parse()?);
+        }
+    }
+
+    if let Some(replacement) = replacement {
+        keymap = replacement.clone();
+    }
+    for binding in bindings.iter() {
+        keymap.bind(binding);
+    }
+    Ok(keymap)
+}
+
+// How the names in the keymap are matched to keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum KeyMode {
+    // By position, so the keypad is in the same place on any layout
+    Scancode,
+    // By the symbol the key types in the layout in use when the emulator starts
+    Keycode,
+}
+
+impl FromStr for KeyMode {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.to_lowercase().as_str() {
+            "scancode" => Ok(KeyMode::Scancode),
+            "keycode" => Ok(KeyMode::Keycode),
+            _ => Err("expected scancode or keycode".to_string()),
+        }
+    }
+}
+
+// Finds the physical keys for each CHIP-8 key in the keymap. Keycodes are
+// turned into the scancode of the key that types them, so key presses are
+// always matched by scancode.
+fn scancodes(keymap: &Keymap, mode: KeyMode) -> Result<Vec<Vec<Scancode>>, String> {
+    (0..16)
+        .map(|chip8_key| {
+            keymap
+                .keys(chip8_key)
+                .iter()
+                .map(|name| {
+                    let scancode = match mode {
+                        KeyMode::Scancode => Scancode::from_name(name),
+                        KeyMode::Keycode => {
+                            Keycode::from_name(name).and_then(Scancode::from_keycode)
+                        }
+                    };
+                    scancode.ok_or_else(|| format!("'{}' is not an SDL key name", name))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// How far a stick has to move from the centre to count as pushed, out of 32767
+const AXIS_THRESHOLD: i16 = 16384;
+
+// A controller button, or an axis pushed one way
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PadInput {
+    Button(Button),
+    Axis(Axis, bool), // True for the positive direction
+}
+
+// A controller input by name, like "a", "dpup" or "leftx-". An axis without
+// a direction is pushed in the positive one, which is how triggers move.
+fn pad_input(name: &str) -> Option<PadInput> {
+    if let Some(axis) = name.strip_suffix('+') {
+        return Axis::from_string(axis).map(|axis| PadInput::Axis(axis, true));
+    }
+    if let Some(axis) = name.strip_suffix('-') {
+        return Axis::from_string(axis).map(|axis| PadInput::Axis(axis, false));
+    }
+    Button::from_string(name)
+        .map(PadInput::Button)
+        .or_else(|| Axis::from_string(name).map(|axis| PadInput::Axis(axis, true)))
+}
+
+// Looks up the controller inputs for each CHIP-8 key in the keymap
+fn pad_inputs(keymap: &Keymap) -> Result<Vec<Vec<PadInput>>, String> {
+    (0..16)
+        .map(|chip8_key| {
+            keymap
+                .keys(chip8_key)
+                .iter()
+                .map(|name| {
+                    pad_input(name).ok_or_else(|| {
+                        format!("'{}' is not an SDL controller button or axis", name)
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+// Keys and controller inputs held down, and the CHIP-8 keys they press. A
+// CHIP-8 key is pressed while any of its keys or inputs are.
+struct Input {
+    keys: Vec<Vec<Scancode>>,       // Keyboard keys for each CHIP-8 key
+    pad_keys: Vec<Vec<PadInput>>,   // Controller inputs for each CHIP-8 key
+    held: Vec<Scancode>,            // Keyboard keys held down
+    pad_held: Vec<(i32, PadInput)>, // Inputs held down, by controller instance id
+}
+
+impl Input {
+    fn press(&mut self, scancode: Scancode) {
+        if !self.held.contains(&scancode) {
+            self.held.push(scancode);
+        }
+    }
+
+    fn release(&mut self, scancode: Scancode) {
+        self.held.retain(|key| *key != scancode);
+    }
+
+    fn press_pad(&mut self, controller: i32, input: PadInput) {
+        if !self.pad_held.contains(&(controller, input)) {
+            self.pad_held.push((controller, input));
+        }
+    }
+
+    fn release_pad(&mut self, controller: i32, input: PadInput) {
+        self.pad_held.retain(|held| *held != (controller, input));
+    }
+
+    // Turns a stick position into at most one direction held down
+    fn move_axis(&mut self, controller: i32, axis: Axis, value: i16) {
+        self.release_pad(controller, PadInput::Axis(axis, true));
+        self.release_pad(controller, PadInput::Axis(axis, false));
+        if value >= AXIS_THRESHOLD {
+            self.press_pad(controller, PadInput::Axis(axis, true));
+        } else if value <= -AXIS_THRESHOLD {
+            self.press_pad(controller, PadInput::Axis(axis, false));
+        }
+    }
+
+    // Lets go of everything held on a controller that was unplugged
+    fn disconnect(&mut self, controller: i32) {
+        self.pad_held.retain(|held| held.0 != controller);
+    }
+
+    // Sets both keypads from what is held down
+    fn update_keypads(&self, chip: &mut chip8::Chip8) {
+        for i in 0..16 {
+            let key = self.keys[i].iter().any(|key| self.held.contains(key));
+            let pad = self.pad_keys[i]
+                .iter()
+                .any(|input| self.pad_held.iter().any(|held| held.1 == *input));
+            chip.key[i] = (key || pad) as u8;
+        }
+        for (i, mapped) in KEYMAP2.iter().enumerate() {
+            chip.key2[i] = self.held.contains(mapped) as u8;
+        }
+    }
+}
+
+// Returns None when the help was asked for
+fn parse_options() -> Result<Option<Options>, OptionError> {
+    let mut machine = MachineOptions::default();
+    let mut config = None;
+    let mut scale = None;
+    let mut integer_scale = None;
+    let mut palette = None;
+    let mut flicker = None;
+    let mut keymap = None;
+    let mut bindings = Vec::new();
+    let mut key_mode = None;
+    let mut pad_keymap = None;
+    let mut pad_bindings = Vec::new();
+    let mut tone = ToneSettings::default();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if machine.parse_arg(&arg, &mut args)? {
+            continue;
+        }
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--config" => config = Some(option_value(&arg, &mut args)?),
+            "--scale" => scale = Some(positive_value(&arg, &mut args)?),
+            "--integer-scale" => {
+                let Switch(enabled) = option_value(&arg, &mut args)?;
+                integer_scale = Some(enabled);
+            }
+            "--palette" => palette = Some(option_value(&arg, &mut args)?),
+            "--flicker" => flicker = Some(option_value(&arg, &mut args)?),
+            "--keymap" => keymap = Some(option_value(&arg, &mut args)?),
+            "--bind" => bindings.push(option_value(&arg, &mut args)?),
+            "--key-mode" => key_mode = Some(option_value(&arg, &mut args)?),
+            "--pad-keymap" => pad_keymap = Some(option_value(&arg, &mut args)?),
+            "--pad-bind" => pad_bindings.push(option_value(&arg, &mut args)?),
+            "--frequency" => tone.frequency = option_value(&arg, &mut args)?,
+            "--waveform" => tone.waveform = option_value(&arg, &mut args)?,
+            "--volume" => tone.volume = option_value(&arg, &mut args)?,
+            _ => return Err(OptionError::UnknownOption(arg)),
+        }
+    }
+
+    if !tone.frequency.is_finite() || tone.frequency <= 0.0 {
+        return Err(OptionError::InvalidValue {
+            name: "--frequency".to_string(),
+            value: tone.frequency.to_string(),
+            reason: "must be above 0".to_string(),
+        });
+    }
+    if !(0.0..=1.0).contains(&tone.volume) {
+        return Err(OptionError::InvalidValue {
+            name: "--volume".to_string(),
+            value: tone.volume.to_string(),
+            reason: "must be from 0.0 to 1.0".to_string(),
+        });
+    }
+    machine.rom_path()?;
+
+    Ok(Some(Options {
+        machine,
+        config,
+        scale,
+        integer_scale,
+        palette,
+        flicker,
+        keymap,
+        bindings,
+        key_mode,
+        pad_keymap,
+        pad_bindings,
+        tone,
+    }))
+}
+
+// Reads the file given with --config, or the default one if there is one
+fn load_config(path: Option<&str>) -> Result<Config, ConfigError> {
+    match path {
+        Some(path) => Config::load(path),
+        None => Config::load_default(),
+    }
+}
+
+// A window setting from the command line, or from the config file when it
+// wasn't given there
+fn setting<T>(option: Option<T>, config: &Config, rom_path: &str, key: &str) -> Option<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if option.is_some() {
+        return option;
+    }
+    match config.value(rom_path, key) {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("config: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
 fn main() {
-    let mut chip = chip8::Chip8::new();
-    // let args: String = env::args().collect();
-    // chip.load(&args);
+    let options = match parse_options() {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}\n\n{}", USAGE, MACHINE_USAGE);
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}\nRun chip8_emu --help to see the options.", err);
+            process::exit(1);
+        }
+    };
 
-    chip.load("roms/PONG2");
+    let config = match load_config(options.config.as_deref()) {
+        Ok(config) => config,
+        Err(err) => {
+            let path = options
+                .config
+                .clone()
+                .or_else(|| Config::default_path().map(|path| path.display().to_string()))
+                .unwrap_or_default();
+            eprintln!("couldn't read config {}: {}", path, err);
+            process::exit(1);
+        }
+    };
+
+    let rom_path = options.machine.rom_path().unwrap_or_default();
+    let mut palette = setting(options.palette, &config, rom_path, "palette").unwrap_or_default();
+    let scale = setting(options.scale, &config, rom_path, "scale");
+    let integer_scale = setting(
+        options.integer_scale.map(Switch),
+        &config,
+        rom_path,
+        "integer-scale",
+    )
+    .is_some_and(|Switch(enabled)| enabled);
+    let mut flicker = FlickerFilter::new(
+        setting(options.flicker, &config, rom_path, "flicker").unwrap_or_default(),
+    );
+    let keymaps = build_keymap(
+        Keymap::default(),
+        &config,
+        rom_path,
+        ("keymap", "bind"),
+        options.keymap.as_ref(),
+        &options.bindings,
+    )
+    .and_then(|keymap| {
+        let pad_keymap = build_keymap(
+            Keymap::controller(),
+            &config,
+            rom_path,
+            ("pad-keymap", "pad-bind"),
+            options.pad_keymap.as_ref(),
+            &options.pad_bindings,
+        )?;
+        Ok((keymap, pad_keymap))
+    });
+    let (keymap, pad_keymap) = match keymaps {
+        Ok(keymaps) => keymaps,
+        Err(err) => {
+            eprintln!("config: {}", err);
+            process::exit(1);
+        }
+    };
+    let key_mode = setting(options.key_mode, &config, rom_path, "key-mode");
+    // position in the built-in palettes, F2 moves on to the next one
+    let mut theme = THEMES.iter().position(|theme| theme.1 == palette);
+
+    let mut chip = options.machine.build();
+
+    // the seed is always reported so any run can be replayed
+    println!("rng seed: {}", chip.seed());
+
+    if let Err(err) = chip.load_path(rom_path) {
+        eprintln!("couldn't load {}: {}", rom_path, err);
+        process::exit(1);
+    }
 
     // sets up window and draws rectangle right now
     let ctx = sdl2::init().unwrap();
     let video_ctx = ctx.video().unwrap();
 
-    let window = match video_ctx
-        .window("CHIP8 EMU", 1024, 512)
+    // keycodes are looked up in the layout, which needs SDL running
+    let keys = match scancodes(&keymap, key_mode.unwrap_or(KeyMode::Scancode)) {
+        Ok(keys) => keys,
+        Err(err) => {
+            eprintln!("invalid keymap: {}", err);
+            process::exit(1);
+        }
+    };
+    let pad_keys = match pad_inputs(&pad_keymap) {
+        Ok(pad_keys) => pad_keys,
+        Err(err) => {
+            eprintln!("invalid controller keymap: {}", err);
+            process::exit(1);
+        }
+    };
+    let mut input = Input {
+        keys,
+        pad_keys,
+        held: Vec::new(),
+        pad_held: Vec::new(),
+    };
+
+    // controllers are opened as they are plugged in, SDL reports the ones
+    // already connected the same way once the subsystem is up
+    let controller_ctx = match ctx.game_controller() {
+        Ok(controller_ctx) => Some(controller_ctx),
+        Err(err) => {
+            eprintln!("couldn't start controller support: {}", err);
+            None
+        }
+    };
+    let mut controllers: Vec<GameController> = Vec::new();
+
+    let mut audio = open_audio(&ctx, options.tone);
+
+    let (width, height) = options.machine.platform.screen_size();
+    let scale = scale
+        .filter(|scale| *scale > 0)
+        .unwrap_or_else(|| fit_scale(&video_ctx, (width, height)));
+
+    let mut window = match video_ctx
+        .window("CHIP8 EMU", width as u32 * scale, height as u32 * scale)
         .position_centered()
+        .resizable()
         .opengl()
         .build()
     {
         Ok(window) => window,
         Err(err) => panic!("failed to create window: {}", err),
     };
+    let _ = window.set_minimum_size(width as u32, height as u32);
 
     let mut renderer = match window.into_canvas().build() {
         Ok(renderer) => renderer,
         Err(err) => panic!("failed to create renderer: {}", err),
     };
 
+    // the texture matches the display mode and is recreated when the
+    // program switches between lo-res and hi-res
     let texture_creator = renderer.texture_creator();
-    let mut sdl_texture = match texture_creator.create_texture(
-        sdl2::pixels::PixelFormatEnum::ARGB8888,
-        sdl2::render::TextureAccess::Streaming,
-        64,
-        32,
-    ) {
-        Ok(texture) => texture,
-        Err(err) => panic!("failed to create renderer: {}", err),
-    };
+    let mut texture_size = (chip.display_width(), chip.display_height());
+    let mut sdl_texture = create_texture(&texture_creator, texture_size);
 
-    let pixels = &mut [0 as u32; 2048];
+    let mut pixels: Vec<u8> = Vec::new();
 
     let mut events = ctx.event_pump().unwrap();
 
+    // set once the program hits an error, the last frame stays on screen
+    let mut halted = false;
+
+    // holding backspace runs the game backwards through the snapshots
+    let mut rewind = Rewind::new(DEFAULT_REWIND_FRAMES);
+    let mut rewinding = false;
+
+    let frame_duration = Duration::from_secs(1) / chip8::TIMER_FREQUENCY;
+    let mut next_frame = Instant::now();
+
     loop {
-        chip.emulate_cycle();
+        if rewinding {
+            // the keypad follows the keys held now, not the ones in the snapshot
+            let keys = (chip.key, chip.key2);
+            if rewind.step_back(&mut chip) && halted {
+                halted = false;
+                let _ = renderer.window_mut().set_title("CHIP8 EMU");
+            }
+            chip.key = keys.0;
+            chip.key2 = keys.1;
+        } else if !halted {
+            match chip.run_frame() {
+                Ok(()) if chip.has_exited() => {
+                    println!("program exited");
+                    let _ = renderer.window_mut().set_title("CHIP8 EMU - exited");
+                    halted = true;
+                }
+                Ok(()) => rewind.push(&chip),
+                Err(err) => {
+                    eprintln!("emulation halted: {}", err);
+                    let _ = renderer
+                        .window_mut()
+                        .set_title(&format!("CHIP8 EMU - halted: {}", err));
+                    halted = true;
+                }
+            }
+        }
+
+        // the buzzer stays quiet while the machine isn't running forwards
+        if let Some(ref mut device) = audio {
+            let mut beeper = device.lock();
+            beeper.0.update(&chip);
+            if rewinding || halted {
+                beeper.0.set_active(false);
+            }
+        }
 
         for event in events.poll_iter() {
             match event {
@@ -88,53 +746,192 @@ fn main() {
                     process::exit(1);
                 }
                 Event::KeyDown {
-                    repeat,
-                    keycode: Some(keycode),
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = false,
+                Event::Window {
+                    win_event: WindowEvent::SizeChanged(..),
+                    ..
+                }
+                | Event::Window {
+                    win_event: WindowEvent::Exposed,
+                    ..
+                } => chip.draw_flag = true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    repeat: false,
+                    ..
+                } => {
+                    let window = renderer.window_mut();
+                    let fullscreen = match window.fullscreen_state() {
+                        FullscreenType::Off => FullscreenType::Desktop,
+                        _ => FullscreenType::Off,
+                    };
+                    if let Err(err) = window.set_fullscreen(fullscreen) {
+                        eprintln!("couldn't switch fullscreen: {}", err);
+                    }
+                    chip.draw_flag = true;
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    repeat: false,
                     ..
                 } => {
-                    if !repeat {
-                        for i in 0..16 {
-                            if keycode == KEYMAP[i] {
-                                chip.key[i] = 1;
+                    let index = FLICKER_MODES
+                        .iter()
+                        .position(|mode| *mode == flicker.mode())
+                        .unwrap_or(0);
+                    let mode = FLICKER_MODES[(index + 1) % FLICKER_MODES.len()];
+                    flicker.set_mode(mode);
+                    println!("flicker: {}", mode);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    repeat: false,
+                    ..
+                } => {
+                    let next = theme.map_or(0, |index| (index + 1) % THEMES.len());
+                    theme = Some(next);
+                    palette = THEMES[next].1;
+                    println!("palette: {}", THEMES[next].0);
+                    chip.draw_flag = true;
+                }
+                Event::KeyDown {
+                    repeat: false,
+                    scancode: Some(scancode),
+                    keymod,
+                    ..
+                } => {
+                    // Ctrl + number saves to a slot, Alt + number loads it
+                    if let Some(slot) = state_slot(scancode) {
+                        if keymod.intersects(LCTRLMOD | RCTRLMOD) {
+                            save_slot(&chip, rom_path, slot);
+                            continue;
+                        }
+                        if keymod.intersects(LALTMOD | RALTMOD) {
+                            if load_slot(&mut chip, rom_path, slot) {
+                                // the keypad follows the keys held now, not
+                                // the ones in the saved state
+                                input.update_keypads(&mut chip);
+                                halted = false;
+                                let _ = renderer.window_mut().set_title("CHIP8 EMU");
                             }
+                            continue;
                         }
                     }
+
+                    input.press(scancode);
+                    input.update_keypads(&mut chip);
                 }
                 Event::KeyUp {
-                    repeat,
-                    keycode: Some(keycode),
+                    repeat: false,
+                    scancode: Some(scancode),
                     ..
                 } => {
-                    if !repeat {
-                        for i in 0..16 {
-                            if keycode == KEYMAP[i] {
-                                chip.key[i] = 0;
+                    input.release(scancode);
+                    input.update_keypads(&mut chip);
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    if let Some(ref controller_ctx) = controller_ctx {
+                        match controller_ctx.open(which) {
+                            Ok(controller) => {
+                                println!("controller connected: {}", controller.name());
+                                controllers.push(controller);
                             }
+                            Err(err) => eprintln!("couldn't open controller: {}", err),
                         }
                     }
                 }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    if let Some(index) = controllers
+                        .iter()
+                        .position(|controller| controller.instance_id() == which)
+                    {
+                        let controller = controllers.remove(index);
+                        println!("controller disconnected: {}", controller.name());
+                    }
+                    input.disconnect(which);
+                    input.update_keypads(&mut chip);
+                }
+                Event::ControllerButtonDown { which, button, .. } => {
+                    input.press_pad(which, PadInput::Button(button));
+                    input.update_keypads(&mut chip);
+                }
+                Event::ControllerButtonUp { which, button, .. } => {
+                    input.release_pad(which, PadInput::Button(button));
+                    input.update_keypads(&mut chip);
+                }
+                Event::ControllerAxisMotion {
+                    which, axis, value, ..
+                } => {
+                    input.move_axis(which, axis, value);
+                    input.update_keypads(&mut chip);
+                }
                 _ => {}
             }
         }
 
-        if chip.draw_flag {
+        // fading pixels need drawing every frame, even when the program
+        // didn't draw anything
+        if flicker.update(&chip.gfx) || chip.draw_flag {
             chip.draw_flag = false;
 
-            for index in 0..pixels.len() {
-                let gfx_pixel = chip.gfx[index].to_owned() as u32;
-                let new_value = (0x00FFFFFF * gfx_pixel) | 0xFF000000;
-                pixels[index] = new_value;
+            let size = (chip.display_width(), chip.display_height());
+            if size != texture_size {
+                texture_size = size;
+                sdl_texture = create_texture(&texture_creator, texture_size);
+            }
+
+            pixels.clear();
+            if chip.platform() == Platform::Chip8X {
+                // lit pixels take their zone's colour, the rest the background
+                let background = VP590_COLORS[chip.background_color() as usize];
+                for index in 0..chip.gfx.len() {
+                    let (x, y) = (index % texture_size.0, index / texture_size.0);
+                    let color = match flicker.pixel(index) {
+                        (0, _) => background,
+                        (_, brightness) => {
+                            let zone = VP590_COLORS[chip.zone_color(x, y) as usize];
+                            0xFF000000 | blend(background, zone, u32::from(brightness), 255)
+                        }
+                    };
+                    pixels.extend_from_slice(&color.to_ne_bytes());
+                }
+            } else {
+                for index in 0..chip.gfx.len() {
+                    let (gfx_pixel, brightness) = flicker.pixel(index);
+                    let color = 0xFF000000 | palette.faded(gfx_pixel, brightness);
+                    pixels.extend_from_slice(&color.to_ne_bytes());
+                }
             }
 
-            let buff: [u8; 8192] = unsafe { transmute(*pixels) };
+            // the display keeps its shape when the window doesn't match it
+            let window_size = renderer
+                .output_size()
+                .unwrap_or((width as u32 * scale, height as u32 * scale));
+            let target = display_rect(window_size, texture_size, integer_scale);
 
             // these return errors that should really be handled
-            let _ = sdl_texture.update(None, &buff, 64 * mem::size_of::<u32>());
-            let _ = renderer.clear();
-            let _ = renderer.copy(&sdl_texture, None, None);
-            let _ = renderer.present();
+            let _ = sdl_texture.update(None, &pixels, texture_size.0 * mem::size_of::<u32>());
+            renderer.clear();
+            if let Some(target) = target {
+                let _ = renderer.copy(&sdl_texture, None, target);
+            }
+            renderer.present();
         }
 
-        thread::sleep(Duration::from_millis(10));
+        // run at a steady 60 frames per second, the core decides how many
+        // instructions fit in each one
+        next_frame += frame_duration;
+        let now = Instant::now();
+        if next_frame > now {
+            thread::sleep(next_frame - now);
+        } else {
+            next_frame = now;
+        }
     }
 }
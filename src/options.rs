@@ -0,0 +1,188 @@
+use std::error::Error;
+use std::fmt;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use chip8::Chip8;
+use platform::Platform;
+use quirks::QUIRK_NAMES;
+
+// Command line handling shared by the frontends. Each binary loops over its
+// arguments, lets MachineOptions take the ROM path and the options that
+// configure the core, and handles whatever is left itself.
+
+// Help text for the options MachineOptions understands
+pub const MACHINE_USAGE: &str = "machine options:
+  --platform NAME    chip8, hires, chip8x, schip or xochip (default chip8)
+  --speed N          instructions per second, up to 10000000 (default 700)
+  --seed N           seed for the random number generator, runs with the same
+                     seed and input are identical
+  --QUIRK on|off     turn one of the platform's quirks on or off, QUIRK is one of
+                     shift-uses-vy, load-store-increments-i, jump-uses-vx,
+                     vf-reset, clip-x, clip-y, display-wait";
+
+// Fastest --speed accepted, well past what any program needs
+pub const MAX_SPEED: u32 = 10_000_000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OptionError {
+    // No ROM path was given
+    MissingRom,
+    // Option at the end of the command line without its value
+    MissingValue(String),
+    // Value that didn't parse, with the parser's explanation
+    InvalidValue {
+        name: String,
+        value: String,
+        reason: String,
+    },
+    UnknownOption(String),
+    // Positional argument after the ROM path
+    UnexpectedArgument(String),
+}
+
+impl fmt::Display for OptionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OptionError::MissingRom => write!(f, "no ROM given"),
+            OptionError::MissingValue(ref name) => write!(f, "{} needs a value", name),
+            OptionError::InvalidValue {
+                ref name,
+                ref value,
+                ref reason,
+            } => write!(f, "invalid {} value '{}': {}", name, value, reason),
+            OptionError::UnknownOption(ref name) => write!(f, "unknown option '{}'", name),
+            OptionError::UnexpectedArgument(ref arg) => {
+                write!(f, "unexpected argument '{}'", arg)
+            }
+        }
+    }
+}
+
+impl Error for OptionError {}
+
+// Takes the value after an option from the argument list and parses it
+pub fn option_value<T, I>(name: &str, args: &mut I) -> Result<T, OptionError>
+where
+    T: FromStr,
+    T::Err: Display,
+    I: Iterator<Item = String>,
+{
+    let value = match args.next() {
+        Some(value) => value,
+        None => return Err(OptionError::MissingValue(name.to_string())),
+    };
+    value
+        .parse()
+        .map_err(|err: T::Err| OptionError::InvalidValue {
+            name: name.to_string(),
+            reason: err.to_string(),
+            value,
+        })
+}
+
+// Like option_value, but fails for zero
+pub fn positive_value<I>(name: &str, args: &mut I) -> Result<u32, OptionError>
+where
+    I: Iterator<Item = String>,
+{
+    let value: u32 = option_value(name, args)?;
+    if value == 0 {
+        return Err(OptionError::InvalidValue {
+            name: name.to_string(),
+            value: value.to_string(),
+            reason: "must be at least 1".to_string(),
+        });
+    }
+    Ok(value)
+}
+
+// An on/off value, also accepts true/false, yes/no and 1/0
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Switch(pub bool);
+
+impl FromStr for Switch {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.to_lowercase().as_str() {
+            "on" | "true" | "yes" | "1" => Ok(Switch(true)),
+            "off" | "false" | "no" | "0" => Ok(Switch(false)),
+            _ => Err("expected on or off".to_string()),
+        }
+    }
+}
+
+// The ROM and how the core should run it
+#[derive(Clone, Debug, Default)]
+pub struct MachineOptions {
+    pub rom_path: Option<String>,
+    pub platform: Platform,
+    pub speed: Option<u32>,
+    pub seed: Option<u64>,
+    // Applied in order on top of the platform's quirks
+    pub quirks: Vec<(&'static str, bool)>,
+}
+
+impl MachineOptions {
+    // Handles the ROM path or one of the machine options, along with its
+    // value. Returns false for any other option so the frontend can look at it.
+    pub fn parse_arg<I>(&mut self, arg: &str, args: &mut I) -> Result<bool, OptionError>
+    where
+        I: Iterator<Item = String>,
+    {
+        let quirk = QUIRK_NAMES
+            .iter()
+            .find(|name| arg.strip_prefix("--") == Some(**name));
+        if let Some(name) = quirk {
+            let Switch(enabled) = option_value(arg, args)?;
+            self.quirks.push((name, enabled));
+            return Ok(true);
+        }
+
+        match arg {
+            "--platform" => self.platform = option_value(arg, args)?,
+            "--speed" => {
+                let speed = positive_value(arg, args)?;
+                if speed > MAX_SPEED {
+                    return Err(OptionError::InvalidValue {
+                        name: arg.to_string(),
+                        value: speed.to_string(),
+                        reason: format!("must be at most {}", MAX_SPEED),
+                    });
+                }
+                self.speed = Some(speed);
+            }
+            "--seed" => self.seed = Some(option_value(arg, args)?),
+            _ if arg.starts_with('-') => return Ok(false),
+            _ if self.rom_path.is_none() => self.rom_path = Some(arg.to_string()),
+            _ => return Err(OptionError::UnexpectedArgument(arg.to_string())),
+        }
+        Ok(true)
+    }
+
+    pub fn rom_path(&self) -> Result<&str, OptionError> {
+        match self.rom_path {
+            Some(ref path) => Ok(path),
+            None => Err(OptionError::MissingRom),
+        }
+    }
+
+    // Creates the machine for the chosen platform, quirks, speed and seed.
+    // The ROM still has to be loaded.
+    pub fn build(&self) -> Chip8 {
+        let mut quirks = self.platform.quirks();
+        for &(name, enabled) in self.quirks.iter() {
+            quirks.set(name, enabled);
+        }
+
+        let mut chip = Chip8::with_platform(self.platform, quirks);
+        if let Some(speed) = self.speed {
+            chip.set_speed(speed);
+        }
+        if let Some(seed) = self.seed {
+            chip.set_seed(seed);
+        }
+        chip
+    }
+}
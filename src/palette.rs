@@ -0,0 +1,176 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+// Display colours as 0xRRGGBB, one for each value a gfx pixel can hold. Plain
+// CHIP-8 only uses the background and foreground, the other two are for
+// pixels lit in the second XO-CHIP bitplane or in both.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+    pub background: u32,
+    pub foreground: u32,
+    pub plane2: u32,  // Lit in the second bitplane only
+    pub overlap: u32, // Lit in both bitplanes
+}
+
+impl Palette {
+    // Fills in the bitplane colours as blends of the two main ones
+    pub fn two_color(background: u32, foreground: u32) -> Self {
+        Palette {
+            background,
+            foreground,
+            plane2: blend(background, foreground, 2, 3),
+            overlap: blend(background, foreground, 1, 3),
+        }
+    }
+
+    // Colour of a gfx pixel
+    pub fn color(&self, pixel: u8) -> u32 {
+        match pixel & 0x3 {
+            0 => self.background,
+            1 => self.foreground,
+            2 => self.plane2,
+            _ => self.overlap,
+        }
+    }
+
+    // Colour of a gfx pixel shown at a brightness from 0 to 255, fading
+    // into the background
+    pub fn faded(&self, pixel: u8, brightness: u8) -> u32 {
+        blend(
+            self.background,
+            self.color(pixel),
+            u32::from(brightness),
+            255,
+        )
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        THEMES[0].1
+    }
+}
+
+// Built-in palettes by name, the first is the default
+pub const THEMES: [(&str, Palette); 5] = [
+    (
+        "classic",
+        Palette {
+            background: 0x000000,
+            foreground: 0xFFFFFF,
+            plane2: 0xAAAAAA,
+            overlap: 0x555555,
+        },
+    ),
+    // Amber monochrome monitor
+    (
+        "amber",
+        Palette {
+            background: 0x1A1000,
+            foreground: 0xFFB000,
+            plane2: 0xB07800,
+            overlap: 0x604000,
+        },
+    ),
+    // Green P1 phosphor
+    (
+        "green",
+        Palette {
+            background: 0x0A140A,
+            foreground: 0x33FF66,
+            plane2: 0x22AA44,
+            overlap: 0x155522,
+        },
+    ),
+    // Four shades of a green handheld LCD
+    (
+        "lcd",
+        Palette {
+            background: 0x9BBC0F,
+            foreground: 0x0F380F,
+            plane2: 0x306230,
+            overlap: 0x8BAC0F,
+        },
+    ),
+    // Octo's default colours
+    (
+        "octo",
+        Palette {
+            background: 0x996600,
+            foreground: 0xFFCC00,
+            plane2: 0xFF6600,
+            overlap: 0x662200,
+        },
+    ),
+];
+
+// Looks up a built-in palette by name
+pub fn theme(name: &str) -> Option<Palette> {
+    let name = name.trim().to_lowercase();
+    THEMES
+        .iter()
+        .find(|theme| theme.0 == name)
+        .map(|theme| theme.1)
+}
+
+// Mixes num/den of the way from one colour to another, channel by channel
+pub fn blend(from: u32, to: u32, num: u32, den: u32) -> u32 {
+    let mut color = 0;
+    for shift in [16, 8, 0].iter() {
+        let a = (from >> shift) & 0xFF;
+        let b = (to >> shift) & 0xFF;
+        color |= ((a * (den - num) + b * num) / den) << shift;
+    }
+    color
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidPalette(pub String);
+
+impl fmt::Display for InvalidPalette {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = THEMES.iter().map(|theme| theme.0).collect();
+        write!(
+            f,
+            "invalid palette '{}', expected a theme ({}) or FOREGROUND,BACKGROUND hex colours \
+             like FFFFFF,000000 with optional XO-CHIP plane 2 and overlap colours after them",
+            self.0,
+            names.join(", ")
+        )
+    }
+}
+
+impl Error for InvalidPalette {}
+
+// Parses a hex colour, with or without a leading '#'
+pub fn parse_color(text: &str) -> Option<u32> {
+    let hex = text.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(hex, 16).ok()
+}
+
+// A theme name, "FOREGROUND,BACKGROUND" or "FOREGROUND,BACKGROUND,PLANE2,OVERLAP"
+impl FromStr for Palette {
+    type Err = InvalidPalette;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if let Some(palette) = theme(text) {
+            return Ok(palette);
+        }
+
+        let colors: Option<Vec<u32>> = text.split(',').map(parse_color).collect();
+        match colors.as_deref() {
+            Some(&[foreground, background]) => Ok(Palette::two_color(background, foreground)),
+            Some(&[foreground, background, plane2, overlap]) => Ok(Palette {
+                background,
+                foreground,
+                plane2,
+                overlap,
+            }),
+            _ => Err(InvalidPalette(text.to_string())),
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use chip8::{DEFAULT_LOAD_ADDRESS, MEMORY_SIZE, SCREEN_HEIGHT, SCREEN_WIDTH};
+use quirks::Quirks;
+
+// The interpreter variant being emulated. It decides which instructions
+// decode and which display modes are available.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Platform {
+    #[default]
+    Chip8, // Original COSMAC VIP CHIP-8
+    HiresChip8, // Two-page HIRES CHIP-8 for the VIP with a 64x64 display
+    Chip8X,     // CHIP-8X for the VIP with the VP-590 colour board
+    SuperChip,  // SUPER-CHIP 1.1 with the 128x64 hi-res mode
+    XoChip,     // Octo's XO-CHIP, SUPER-CHIP plus 64 KB, bitplanes and audio
+}
+
+pub const PLATFORMS: [Platform; 5] = [
+    Platform::Chip8,
+    Platform::HiresChip8,
+    Platform::Chip8X,
+    Platform::SuperChip,
+    Platform::XoChip,
+];
+
+impl Platform {
+    // Name used on the command line
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip8",
+            Platform::HiresChip8 => "hires",
+            Platform::Chip8X => "chip8x",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+
+    // Quirks the platform's own interpreter had
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 | Platform::HiresChip8 | Platform::Chip8X => Quirks::cosmac_vip(),
+            Platform::SuperChip => Quirks::super_chip(),
+            Platform::XoChip => Quirks::xo_chip(),
+        }
+    }
+
+    // Whether the SUPER-CHIP instructions (scrolling, hi-res, big font,
+    // RPL flags, exit) are available
+    pub fn has_super_chip(self) -> bool {
+        match self {
+            Platform::Chip8 | Platform::HiresChip8 | Platform::Chip8X => false,
+            Platform::SuperChip | Platform::XoChip => true,
+        }
+    }
+
+    // Whether the XO-CHIP instructions (long I loads, register ranges,
+    // bitplanes, audio patterns, scroll up) are available
+    pub fn has_xo_chip(self) -> bool {
+        match self {
+            Platform::Chip8 | Platform::HiresChip8 | Platform::Chip8X | Platform::SuperChip => {
+                false
+            }
+            Platform::XoChip => true,
+        }
+    }
+
+    // Bytes of addressable memory
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::HiresChip8 | Platform::Chip8X | Platform::SuperChip => {
+                MEMORY_SIZE
+            }
+            Platform::XoChip => 0x10000,
+        }
+    }
+
+    // Width and height of the display outside the SUPER-CHIP hi-res mode
+    pub fn screen_size(self) -> (usize, usize) {
+        match self {
+            Platform::HiresChip8 => (SCREEN_WIDTH, 64),
+            Platform::Chip8 | Platform::Chip8X | Platform::SuperChip | Platform::XoChip => {
+                (SCREEN_WIDTH, SCREEN_HEIGHT)
+            }
+        }
+    }
+
+    // Where ROMs get loaded unless the frontend picks another address. The
+    // CHIP-8X interpreter is larger and its programs start at 0x300.
+    pub fn load_address(self) -> u16 {
+        match self {
+            Platform::Chip8X => 0x300,
+            Platform::Chip8 | Platform::HiresChip8 | Platform::SuperChip | Platform::XoChip => {
+                DEFAULT_LOAD_ADDRESS
+            }
+        }
+    }
+
+    // Bytes between the load address and the first instruction executed.
+    // HIRES CHIP-8 ROMs start with a jump into the interpreter patch that
+    // widened the display, the program itself begins at 0x2C0.
+    pub fn entry_offset(self) -> u16 {
+        match self {
+            Platform::HiresChip8 => 0xC0,
+            Platform::Chip8 | Platform::Chip8X | Platform::SuperChip | Platform::XoChip => 0,
+        }
+    }
+
+    // Identifier stored in save states
+    pub fn id(self) -> u8 {
+        match self {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 1,
+            Platform::XoChip => 2,
+            Platform::HiresChip8 => 3,
+            Platform::Chip8X => 4,
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnknownPlatform(pub String);
+
+impl fmt::Display for UnknownPlatform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = PLATFORMS.iter().map(|platform| platform.name()).collect();
+        write!(
+            f,
+            "unknown platform '{}', expected one of {}",
+            self.0,
+            names.join(", ")
+        )
+    }
+}
+
+impl Error for UnknownPlatform {}
+
+impl FromStr for Platform {
+    type Err = UnknownPlatform;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_lowercase().as_str() {
+            "chip8" | "chip-8" => Ok(Platform::Chip8),
+            "hires" | "hires-chip8" | "hires-chip-8" => Ok(Platform::HiresChip8),
+            "chip8x" | "chip-8x" => Ok(Platform::Chip8X),
+            "schip" | "superchip" | "super-chip" => Ok(Platform::SuperChip),
+            "xochip" | "xo-chip" => Ok(Platform::XoChip),
+            _ => Err(UnknownPlatform(name.to_string())),
+        }
+    }
+}
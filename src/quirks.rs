@@ -0,0 +1,135 @@
+// Behaviours that differ between CHIP-8 interpreters. ROMs are written
+// against a particular interpreter, so pick the preset that matches it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    // 8XY6/8XYE shift VY and store the result in VX, rather than shifting VX
+    pub shift_uses_vy: bool,
+    // How far FX55/FX65 move I on from where the transfer started
+    pub load_store_increments_i: IndexIncrement,
+    // BNNN behaves as BXNN and jumps to XNN + VX instead of NNN + V0
+    pub jump_uses_vx: bool,
+    // 8XY1/8XY2/8XY3 reset VF to 0
+    pub vf_reset: bool,
+    // DXYN clips sprites at the left/right edges instead of wrapping them around
+    pub clip_x: bool,
+    // DXYN clips sprites at the top/bottom edges instead of wrapping them around
+    pub clip_y: bool,
+    // DXYN waits for the next 60 Hz vertical blank before drawing
+    pub display_wait: bool,
+}
+
+// Names of the quirks on the command line, in field order
+pub const QUIRK_NAMES: [&str; 7] = [
+    "shift-uses-vy",
+    "load-store-increments-i",
+    "jump-uses-vx",
+    "vf-reset",
+    "clip-x",
+    "clip-y",
+    "display-wait",
+];
+
+// What FX55/FX65 do to I after transferring V0 to VX
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexIncrement {
+    None,     // I is left alone
+    X,        // I ends up on the last register transferred
+    XPlusOne, // I ends up one past the last register transferred
+}
+
+impl IndexIncrement {
+    // Amount added to I after transferring V0 to VX
+    pub fn amount(self, x: u16) -> u16 {
+        match self {
+            IndexIncrement::None => 0,
+            IndexIncrement::X => x,
+            IndexIncrement::XPlusOne => x + 1,
+        }
+    }
+}
+
+impl Quirks {
+    // Turns a quirk on or off by its name in QUIRK_NAMES, returns false for
+    // an unknown name. Turning load-store-increments-i on gives the original
+    // interpreter's X + 1.
+    pub fn set(&mut self, name: &str, enabled: bool) -> bool {
+        if name == "load-store-increments-i" {
+            self.load_store_increments_i = if enabled {
+                IndexIncrement::XPlusOne
+            } else {
+                IndexIncrement::None
+            };
+            return true;
+        }
+
+        let quirk = match name {
+            "shift-uses-vy" => &mut self.shift_uses_vy,
+            "jump-uses-vx" => &mut self.jump_uses_vx,
+            "vf-reset" => &mut self.vf_reset,
+            "clip-x" => &mut self.clip_x,
+            "clip-y" => &mut self.clip_y,
+            "display-wait" => &mut self.display_wait,
+            _ => return false,
+        };
+        *quirk = enabled;
+        true
+    }
+
+    // The original interpreter on the RCA COSMAC VIP
+    pub fn cosmac_vip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: IndexIncrement::XPlusOne,
+            jump_uses_vx: false,
+            vf_reset: true,
+            clip_x: true,
+            clip_y: true,
+            display_wait: true,
+        }
+    }
+
+    // CHIP-48 on the HP-48 calculators, which gets FX55/FX65 off by one
+    pub fn chip48() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: IndexIncrement::X,
+            jump_uses_vx: true,
+            vf_reset: false,
+            clip_x: true,
+            clip_y: true,
+            display_wait: false,
+        }
+    }
+
+    // SUPER-CHIP 1.1, also on the HP-48
+    pub fn super_chip() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            load_store_increments_i: IndexIncrement::None,
+            jump_uses_vx: true,
+            vf_reset: false,
+            clip_x: true,
+            clip_y: true,
+            display_wait: false,
+        }
+    }
+
+    // XO-CHIP as implemented by Octo
+    pub fn xo_chip() -> Self {
+        Quirks {
+            shift_uses_vy: true,
+            load_store_increments_i: IndexIncrement::XPlusOne,
+            jump_uses_vx: false,
+            vf_reset: false,
+            clip_x: false,
+            clip_y: false,
+            display_wait: false,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::cosmac_vip()
+    }
+}
@@ -0,0 +1,289 @@
+use std::collections::VecDeque;
+
+use chip8::{Chip8, TIMER_FREQUENCY};
+
+// Five minutes of history at one snapshot per frame
+pub const DEFAULT_REWIND_FRAMES: usize = 5 * 60 * TIMER_FREQUENCY as usize;
+
+/*
+    Bounded history of machine snapshots for running a game backwards.
+
+    Only the newest snapshot is kept in full. Every older one is stored as the
+    XOR of itself and the snapshot after it, run-length encoded, so a frame
+    where a handful of registers changed costs a few dozen bytes instead of
+    the full ~6 KB state. Stepping back decodes the newest delta against the
+    newest snapshot, which means the oldest deltas can be dropped from the
+    front once the buffer is full without breaking the chain.
+
+    Switching between lo-res and hi-res changes the size of the gfx buffer and
+    so of the snapshot. Two snapshots of different sizes can't be XORed, so the
+    older one is kept in full instead.
+*/
+pub struct Rewind {
+    capacity: usize,         // Most snapshots kept, including the newest
+    latest: Option<Vec<u8>>, // Newest snapshot in full
+    deltas: VecDeque<Step>,  // Older snapshots, oldest first
+}
+
+// How to get from a snapshot to the one before it
+enum Step {
+    Delta(Vec<u8>), // XOR with the newer snapshot, encoded by encode_delta
+    Full(Vec<u8>),  // The older snapshot itself, when the sizes differ
+}
+
+impl Step {
+    fn len(&self) -> usize {
+        match *self {
+            Step::Delta(ref bytes) | Step::Full(ref bytes) => bytes.len(),
+        }
+    }
+}
+
+impl Rewind {
+    pub fn new(capacity: usize) -> Self {
+        Rewind {
+            capacity: capacity.max(1),
+            latest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    // Records the machine as the newest snapshot, call once per frame
+    pub fn push(&mut self, chip: &Chip8) {
+        let state = chip.save_state();
+
+        if let Some(latest) = self.latest.take() {
+            if latest.len() == state.len() {
+                self.deltas
+                    .push_back(Step::Delta(encode_delta(&latest, &state)));
+            } else {
+                // the display mode changed, keep the older snapshot whole
+                self.deltas.push_back(Step::Full(latest));
+            }
+        }
+        self.latest = Some(state);
+
+        while self.deltas.len() + 1 > self.capacity {
+            self.deltas.pop_front();
+        }
+    }
+
+    // Restores the snapshot before the newest one and drops the newest.
+    // Returns false once there is no older history left.
+    pub fn step_back(&mut self, chip: &mut Chip8) -> bool {
+        let step = match self.deltas.pop_back() {
+            Some(step) => step,
+            None => return false,
+        };
+
+        let previous = match (self.latest.as_mut(), step) {
+            (Some(latest), Step::Delta(delta)) => {
+                apply_delta(latest, &delta);
+                latest
+            }
+            (Some(latest), Step::Full(state)) => {
+                *latest = state;
+                latest
+            }
+            (None, _) => return false,
+        };
+
+        // snapshots come from save_state on the same machine so restoring
+        // them can't fail, if it does the history is unusable anyway
+        if chip.load_state(previous).is_err() {
+            self.clear();
+            return false;
+        }
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+    }
+
+    // Number of frames that can currently be stepped back
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    // Bytes used by the stored history, for showing in frontends
+    pub fn memory_usage(&self) -> usize {
+        let latest = self.latest.as_ref().map_or(0, |latest| latest.len());
+        latest + self.deltas.iter().map(|step| step.len()).sum::<usize>()
+    }
+}
+
+/*
+    Delta encoding: the XOR of two equally sized snapshots as a sequence of
+    (unchanged byte count, changed byte count, changed bytes) records, with
+    both counts as LEB128 varints. Trailing unchanged bytes are omitted.
+*/
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    let mut pos = 0;
+
+    while pos < old.len() {
+        let skip_start = pos;
+        while pos < old.len() && old[pos] == new[pos] {
+            pos += 1;
+        }
+        if pos == old.len() {
+            break;
+        }
+
+        let run_start = pos;
+        while pos < old.len() && old[pos] != new[pos] {
+            pos += 1;
+        }
+
+        write_varint(&mut delta, run_start - skip_start);
+        write_varint(&mut delta, pos - run_start);
+        for index in run_start..pos {
+            delta.push(old[index] ^ new[index]);
+        }
+    }
+
+    delta
+}
+
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut pos = 0;
+    let mut cursor = 0;
+
+    while cursor < delta.len() {
+        pos += read_varint(delta, &mut cursor);
+        let len = read_varint(delta, &mut cursor);
+        for byte in state[pos..pos + len].iter_mut() {
+            *byte ^= delta[cursor];
+            cursor += 1;
+        }
+        pos += len;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], cursor: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*cursor];
+        *cursor += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use platform::Platform;
+
+    fn round_trip(old: &[u8], new: &[u8]) -> Vec<u8> {
+        let delta = encode_delta(old, new);
+        let mut restored = new.to_vec();
+        apply_delta(&mut restored, &delta);
+        restored
+    }
+
+    #[test]
+    fn varints_round_trip() {
+        for &value in [0, 1, 127, 128, 129, 255, 300, 16383, 16384, 1 << 20].iter() {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, value);
+            assert_eq!(
+                bytes.len(),
+                1 + (value >= 128) as usize + (value >= 16384) as usize
+            );
+
+            let mut cursor = 0;
+            assert_eq!(read_varint(&bytes, &mut cursor), value);
+            assert_eq!(cursor, bytes.len());
+        }
+    }
+
+    #[test]
+    fn long_runs_and_gaps() {
+        // runs and gaps of 128 and more need two byte varints
+        let old = vec![0u8; 1000];
+        let mut new = old.clone();
+        for byte in new[200..400].iter_mut() {
+            *byte = 0xAA;
+        }
+        for byte in new[700..701].iter_mut() {
+            *byte = 1;
+        }
+
+        let delta = encode_delta(&old, &new);
+        assert_eq!(delta.len(), 2 + 2 + 200 + 2 + 1 + 1);
+        assert_eq!(round_trip(&old, &new), old);
+    }
+
+    #[test]
+    fn runs_at_both_ends() {
+        let old: Vec<u8> = (0..=255).collect();
+        let mut new = old.clone();
+        new[0] ^= 1;
+        new[1] ^= 2;
+        new[254] ^= 4;
+        new[255] ^= 8;
+
+        let delta = encode_delta(&old, &new);
+        assert_eq!(&delta[..4], &[0, 2, 1, 2]);
+        assert_eq!(round_trip(&old, &new), old);
+
+        // everything changed, a single run with no gap
+        let new: Vec<u8> = old.iter().map(|byte| !byte).collect();
+        assert_eq!(round_trip(&old, &new), old);
+
+        // nothing changed
+        assert!(encode_delta(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn steps_back_to_earlier_states_after_eviction() {
+        let platform = Platform::SuperChip;
+        let mut chip = Chip8::with_platform(platform, platform.quirks());
+        chip.load_bytes(&[
+            0x70, 0x01, // ADD V0, 1
+            0xC1, 0xFF, // RND V1, 0xFF
+            0xA3, 0x00, // LD I, 0x300
+            0xF1, 0x55, // LD [I], V1
+            0x12, 0x00, // JP 0x200
+        ])
+        .unwrap();
+
+        let capacity = 10;
+        let mut rewind = Rewind::new(capacity);
+        let mut states = Vec::new();
+        for _ in 0..25 {
+            for _ in 0..7 {
+                chip.emulate_cycle().unwrap();
+            }
+            states.push(chip.save_state());
+            rewind.push(&chip);
+        }
+        assert_eq!(rewind.len(), capacity - 1);
+
+        for back in 1..capacity {
+            assert!(rewind.step_back(&mut chip));
+            assert_eq!(chip.save_state(), states[states.len() - 1 - back]);
+        }
+        assert!(!rewind.step_back(&mut chip));
+        assert_eq!(chip.save_state(), states[states.len() - capacity]);
+    }
+}
@@ -0,0 +1,358 @@
+/*
+    Save state format, produced by Chip8::save_state and read back by
+    Chip8::load_state. All multi-byte values are little endian.
+
+    size  field
+    4     magic, the ASCII bytes "C8ST"
+    2     format version, currently 4
+    1     platform (0 CHIP-8, 1 SUPER-CHIP, 2 XO-CHIP, 3 HIRES CHIP-8,
+          4 CHIP-8X)
+    mem   memory, 4096 bytes or 65536 on XO-CHIP
+    16    V0-VF
+    32    stack, 16 u16 entries
+    2     sp
+    2     pc
+    2     i
+    2     opcode
+    1     delay timer
+    1     sound timer
+    1     vblank ready flag (0 or 1)
+    8     rng seed
+    8     rng state
+    1     hi-res flag (0 or 1)
+    w*h   gfx, one byte per pixel with a bit per bitplane, 64x32 or 128x64
+          depending on the hi-res flag, 64x64 on HIRES CHIP-8
+    16    keypad
+    16    RPL user flags
+    1     exited flag (0 or 1)
+    1     selected bitplanes
+    1     audio pattern loaded flag (0 or 1)
+    16    audio pattern, zero when not loaded
+    1     pitch
+    1     CHIP-8X background colour index
+    256   CHIP-8X zone colours, 8 zones per row for 32 rows
+    16    second keypad
+    1     output port
+    1     input port latched flag (0 or 1)
+    1     input port byte, zero when not latched
+    4     CRC-32 (IEEE) of every byte before it
+
+    Quirks, speed and load address are configuration rather than machine
+    state and are not saved. A state with a different version is rejected,
+    any change to the layout above has to bump STATE_VERSION. A state can
+    only be loaded into a machine emulating the same platform.
+
+    Version history:
+    1     initial format, CHIP-8 only
+    2     platform, hi-res flag, RPL flags and exited flag
+    3     platform moved before memory, XO-CHIP memory size, bitplanes, audio
+    4     CHIP-8X colours, second keypad and ports
+*/
+
+use error::StateError;
+
+pub const STATE_MAGIC: [u8; 4] = *b"C8ST";
+pub const STATE_VERSION: u16 = 4;
+
+// Appends fields to a state buffer
+pub struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        let mut writer = StateWriter { buf: Vec::new() };
+        writer.bytes(&STATE_MAGIC);
+        writer.u16(STATE_VERSION);
+        writer
+    }
+
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    // Appends the checksum and returns the finished state
+    pub fn finish(mut self) -> Vec<u8> {
+        let checksum = crc32(&self.buf);
+        self.bytes(&checksum.to_le_bytes());
+        self.buf
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Reads fields back out of a state buffer. The header and checksum are
+// verified up front so the fields can be read without further checks
+// beyond running out of data.
+pub struct StateReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(state: &'a [u8]) -> Result<Self, StateError> {
+        if state.len() < STATE_MAGIC.len() + 2 + 4 {
+            return Err(StateError::Truncated);
+        }
+        if state[..4] != STATE_MAGIC {
+            return Err(StateError::BadMagic);
+        }
+
+        let version = u16::from_le_bytes([state[4], state[5]]);
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let (body, checksum) = state.split_at(state.len() - 4);
+        let checksum = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        if crc32(body) != checksum {
+            return Err(StateError::ChecksumMismatch);
+        }
+
+        Ok(StateReader { buf: body, pos: 6 })
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.pos + len > self.buf.len() {
+            return Err(StateError::Truncated);
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        let mut value = [0; 8];
+        value.copy_from_slice(self.bytes(8)?);
+        Ok(u64::from_le_bytes(value))
+    }
+
+    // Fails if there is data left over after the last field
+    pub fn finish(self) -> Result<(), StateError> {
+        if self.pos != self.buf.len() {
+            return Err(StateError::TrailingData);
+        }
+        Ok(())
+    }
+}
+
+// CRC-32 with the IEEE polynomial, as used by zip and png
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::Chip8;
+    use instruction::decode;
+    use platform::Platform;
+
+    // A SUPER-CHIP machine in hi-res with registers, stack, gfx and the rng
+    // all away from their reset values
+    fn busy_machine() -> Chip8 {
+        let platform = Platform::SuperChip;
+        let mut chip = Chip8::with_platform(platform, platform.quirks());
+        chip.set_seed(1234);
+        chip.load_bytes(&[
+            0x00, 0xFF, // HIGH
+            0x61, 0x2A, // LD V1, 0x2A
+            0xA3, 0x00, // LD I, 0x300
+            0xF0, 0x29, // LD F, V0
+            0xD1, 0x15, // DRW V1, V1, 5
+            0xC2, 0xFF, // RND V2, 0xFF
+            0x22, 0x10, // CALL 0x210
+            0x00, 0x00, //
+            0x63, 0x07, // LD V3, 0x07
+            0x12, 0x12, // JP 0x212
+        ])
+        .unwrap();
+        for _ in 0..8 {
+            chip.emulate_cycle().unwrap();
+        }
+        chip
+    }
+
+    fn fresh_machine() -> Chip8 {
+        let platform = Platform::SuperChip;
+        Chip8::with_platform(platform, platform.quirks())
+    }
+
+    // Replaces the checksum so a change to the body gets past it
+    fn resign(state: &[u8]) -> Vec<u8> {
+        let mut writer = StateWriter { buf: Vec::new() };
+        writer.bytes(state);
+        writer.finish()
+    }
+
+    fn body(state: &[u8]) -> &[u8] {
+        &state[..state.len() - 4]
+    }
+
+    #[test]
+    fn round_trip_restores_the_machine() {
+        let mut original = busy_machine();
+        let state = original.save_state();
+
+        let mut restored = fresh_machine();
+        assert_eq!(restored.load_state(&state), Ok(()));
+
+        assert_eq!(restored.v(), original.v());
+        assert_eq!(restored.i(), original.i());
+        assert_eq!(restored.pc(), original.pc());
+        assert_eq!(restored.stack(), original.stack());
+        assert_eq!(restored.memory(), original.memory());
+        assert_eq!(restored.seed(), 1234);
+        assert!(restored.is_hires());
+        assert_eq!(restored.gfx.len(), 128 * 64);
+        assert_eq!(restored.gfx, original.gfx);
+        assert!(restored.gfx.iter().any(|pixel| *pixel != 0));
+        assert_eq!(restored.save_state(), state);
+
+        // the rng carries on from the same point
+        for machine in [&mut original, &mut restored].iter_mut() {
+            for &opcode in [0xC4FF, 0xC5FF, 0xC6FF].iter() {
+                machine.execute(decode(opcode).unwrap()).unwrap();
+            }
+        }
+        assert_eq!(restored.v(), original.v());
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut state = busy_machine().save_state();
+        state[4..6].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+        let state = resign(body(&state));
+
+        let mut chip = fresh_machine();
+        assert_eq!(
+            chip.load_state(&state),
+            Err(StateError::UnsupportedVersion(STATE_VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_magic() {
+        let mut state = busy_machine().save_state();
+        state[0] = b'X';
+        assert_eq!(
+            fresh_machine().load_state(&state),
+            Err(StateError::BadMagic)
+        );
+    }
+
+    #[test]
+    fn rejects_corrupt_states() {
+        let state = busy_machine().save_state();
+        for &index in [6, 7, 100, state.len() / 2, state.len() - 5, state.len() - 1].iter() {
+            let mut corrupt = state.clone();
+            corrupt[index] ^= 0x10;
+            assert_eq!(
+                fresh_machine().load_state(&corrupt),
+                Err(StateError::ChecksumMismatch),
+                "byte {}",
+                index
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_states() {
+        let state = busy_machine().save_state();
+        let body = body(&state);
+
+        assert_eq!(
+            fresh_machine().load_state(&state[..5]),
+            Err(StateError::Truncated)
+        );
+        for &cut in [1, 16, 300, body.len() - 7].iter() {
+            let truncated = resign(&body[..body.len() - cut]);
+            assert_eq!(
+                fresh_machine().load_state(&truncated),
+                Err(StateError::Truncated),
+                "{} bytes cut",
+                cut
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_trailing_data() {
+        let state = busy_machine().save_state();
+        let mut long = body(&state).to_vec();
+        long.push(0);
+        assert_eq!(
+            fresh_machine().load_state(&resign(&long)),
+            Err(StateError::TrailingData)
+        );
+    }
+
+    #[test]
+    fn rejects_other_platforms() {
+        let state = busy_machine().save_state();
+        for &platform in [Platform::Chip8, Platform::XoChip, Platform::Chip8X].iter() {
+            let mut chip = Chip8::with_platform(platform, platform.quirks());
+            assert_eq!(chip.load_state(&state), Err(StateError::PlatformMismatch));
+        }
+    }
+
+    #[test]
+    fn failed_loads_leave_the_machine_alone() {
+        let state = busy_machine().save_state();
+        let mut chip = fresh_machine();
+        chip.load_bytes(&[0x60, 0x01, 0x12, 0x00]).unwrap();
+        chip.emulate_cycle().unwrap();
+        let before = chip.save_state();
+
+        let mut corrupt = state.clone();
+        corrupt[200] ^= 1;
+        let mut long = body(&state).to_vec();
+        long.push(0);
+        let mut version = state.clone();
+        version[4] ^= 1;
+        let bad = [
+            corrupt,
+            resign(&body(&state)[..body(&state).len() - 1]),
+            resign(&long),
+            resign(body(&version)),
+        ];
+
+        for bad in bad.iter() {
+            assert!(chip.load_state(bad).is_err());
+            assert_eq!(chip.save_state(), before);
+        }
+    }
+}